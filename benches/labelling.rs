@@ -0,0 +1,131 @@
+//! Compares the two-pass union-find labeller against the queue-based flood
+//! fill it replaced. Run with `cargo bench --bench labelling`.
+
+use imgseg::{ccl, Connectivity};
+use std::{collections, time};
+
+const WIDTH: u32 = 5472;
+const HEIGHT: u32 = 3648;
+const ROUNDS: u32 = 3;
+
+/// The original `LinkedList` breadth-first flood fill, kept as the baseline.
+fn flood_fill(
+    width: u32,
+    height: u32,
+    seeds: &[bool],
+    grows: &[bool],
+    connectivity: Connectivity,
+) -> Vec<Vec<(u32, u32)>> {
+    let mut vis = vec![false; (width * height) as usize];
+    let mut blobs = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            if vis[(y * width + x) as usize] || !seeds[(y * width + x) as usize] {
+                continue;
+            }
+            let mut q = collections::LinkedList::new();
+            let mut blob = vec![(x, y)];
+            q.push_back((x, y));
+            while let Some((x, y)) = q.pop_front() {
+                if vis[(y * width + x) as usize] {
+                    continue;
+                }
+                vis[(y * width + x) as usize] = true;
+                blob.push((x, y));
+                for &(dx, dy) in connectivity.offsets() {
+                    let (cx, cy) = (x.wrapping_add_signed(dx), y.wrapping_add_signed(dy));
+                    if cx >= width
+                        || cy >= height
+                        || vis[(cy * width + cx) as usize]
+                        || !grows[(cy * width + cx) as usize]
+                    {
+                        continue;
+                    }
+                    q.push_back((cx, cy));
+                }
+            }
+            blobs.push(blob);
+        }
+    }
+    blobs
+}
+
+/// Scatters dark discs with a lighter rim and some speckle over the frame.
+fn masks() -> (Vec<bool>, Vec<bool>) {
+    let mut state = 0x2545f4914f6cdd1du64;
+    let mut rand = move |n: u32| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % n as u64) as u32
+    };
+    let (w, h) = (WIDTH as usize, HEIGHT as usize);
+    let mut img = vec![200u8; w * h];
+    for _ in 0..40000 {
+        let (cx, cy, r) = (rand(WIDTH), rand(HEIGHT), 1 + rand(8));
+        for y in cy.saturating_sub(r + 1)..(cy + r + 2).min(HEIGHT) {
+            for x in cx.saturating_sub(r + 1)..(cx + r + 2).min(WIDTH) {
+                let d = (x as f64 - cx as f64).hypot(y as f64 - cy as f64);
+                let px = &mut img[y as usize * w + x as usize];
+                if d <= r as f64 {
+                    *px = 20 + rand(20) as u8;
+                } else if d <= r as f64 + 1.0 {
+                    *px = (*px).min(50 + rand(20) as u8);
+                }
+            }
+        }
+    }
+    for _ in 0..200000 {
+        img[rand(WIDTH * HEIGHT) as usize] = rand(60) as u8;
+    }
+    img.iter().map(|&px| (px <= 40, px <= 60)).unzip()
+}
+
+fn bench(
+    name: &str,
+    f: impl Fn() -> Vec<Vec<(u32, u32)>>,
+) -> (Vec<Vec<(u32, u32)>>, time::Duration) {
+    let mut best = time::Duration::MAX;
+    let mut out = Vec::new();
+    for _ in 0..ROUNDS {
+        let start = time::Instant::now();
+        out = f();
+        best = best.min(start.elapsed());
+    }
+    println!(
+        "{:<12} {:>9.3} ms {:>8.1} Mpx/s ({} blobs)",
+        name,
+        best.as_secs_f64() * 1e3,
+        (WIDTH * HEIGHT) as f64 / best.as_secs_f64() / 1e6,
+        out.len()
+    );
+    (out, best)
+}
+
+fn main() {
+    let (seeds, grows) = masks();
+    println!("{}x{} frame, best of {}", WIDTH, HEIGHT, ROUNDS);
+    for connectivity in [Connectivity::Four, Connectivity::Eight] {
+        println!("{}-connectivity", connectivity);
+        let (before, t_before) = bench("flood fill", || {
+            flood_fill(WIDTH, HEIGHT, &seeds, &grows, connectivity)
+        });
+        let (after, t_after) = bench("union-find", || {
+            ccl::hysteresis(WIDTH, HEIGHT, &seeds, &grows, connectivity)
+        });
+        // The flood fill lists its seed twice and the rest in visiting order.
+        let before: Vec<_> = before
+            .into_iter()
+            .map(|mut blob| {
+                blob.remove(0);
+                blob.sort_unstable_by_key(|&(x, y)| (y, x));
+                blob
+            })
+            .collect();
+        assert!(before == after, "labellers disagree");
+        println!(
+            "speedup      {:>9.2}x",
+            t_before.as_secs_f64() / t_after.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use crate::{
+    morphology::Element,
+    plane::{Depth, Plane},
+};
+use std::{collections::VecDeque, fmt, str};
+
+/// How to estimate the slowly varying background behind the objects.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Background {
+    #[default]
+    None,
+    /// ImageJ's rolling ball: the surface traced by a ball of the given
+    /// radius rolled along the background side of the image, after a 3x3
+    /// mean and with the image shrunk for large radii as ImageJ does.
+    RollingBall { radius: f64 },
+    /// Grayscale closing with a flat disk of the given radius for dark
+    /// objects, opening for bright ones. Subtracting it is the black or
+    /// white top-hat transform.
+    TopHat { radius: u32 },
+}
+
+impl Background {
+    /// Estimates the background of an image with dark objects on a light
+    /// background, or returns `None` when subtraction is off.
+    pub fn estimate(self, img: &Plane) -> Option<Plane> {
+        if self == Background::None {
+            return None;
+        }
+        let (width, height) = (img.width(), img.height());
+        let full = img.depth.full_scale() as f32;
+        // Both estimators work from below, so run them on the inverted image
+        // where the background is dark.
+        let inverted: Vec<f32> = img.samples().iter().map(|&v| full - v).collect();
+        let estimate = match self {
+            Background::None => unreachable!("handled above"),
+            Background::RollingBall { radius } => rolling_ball(&inverted, width, height, radius),
+            Background::TopHat { radius } => {
+                let eroded = gray_sweep(&inverted, width, radius, false);
+                gray_sweep(&eroded, width, radius, true)
+            }
+        };
+        let samples = estimate
+            .iter()
+            .map(|&v| match img.depth {
+                Depth::F32 => full - v,
+                Depth::U8 | Depth::U16 => full - v.round().clamp(0.0, full),
+            })
+            .collect();
+        Plane::new(img.depth, width, height, samples)
+    }
+}
+
+/// Subtracts a light background from an image with dark objects, so that
+/// the background ends up at full scale and the objects keep their depth
+/// below it. Integer samples are clamped to their range.
+pub fn subtract(img: &mut Plane, background: &Plane) {
+    let full = img.depth.full_scale() as f32;
+    let integer = img.depth != Depth::F32;
+    for (v, &bg) in img.samples_mut().iter_mut().zip(background.samples()) {
+        *v = *v - bg + full;
+        if integer {
+            *v = v.clamp(0.0, full);
+        }
+    }
+}
+
+impl str::FromStr for Background {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "none" => Ok(Background::None),
+            Some(("rolling-ball", radius)) => radius
+                .parse::<f64>()
+                .ok()
+                .filter(|r| r.is_finite() && *r > 0.0)
+                .map(|radius| Background::RollingBall { radius })
+                .ok_or_else(|| format!("invalid rolling ball radius {radius:?}")),
+            Some(("top-hat", radius)) => radius
+                .parse()
+                .ok()
+                .filter(|&r| r > 0)
+                .map(|radius| Background::TopHat { radius })
+                .ok_or_else(|| format!("invalid top-hat radius {radius:?}")),
+            _ => Err(format!(
+                "unknown background {s:?} (expected none, rolling-ball:<radius> or top-hat:<radius>)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Background {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Background::None => f.write_str("none"),
+            Background::RollingBall { radius } => write!(f, "rolling-ball:{radius}"),
+            Background::TopHat { radius } => write!(f, "top-hat:{radius}"),
+        }
+    }
+}
+
+/// Grayscale erosion (`max == false`) or dilation with a flat disk, using a
+/// running extreme along each row of the disk. Windows are cropped at the
+/// image border.
+fn gray_sweep(img: &[f32], width: u32, radius: u32, max: bool) -> Vec<f32> {
+    let w = width as usize;
+    let h = img.len() / w.max(1);
+    let better = |a: f32, b: f32| if max { a >= b } else { a <= b };
+    let init = if max {
+        f32::NEG_INFINITY
+    } else {
+        f32::INFINITY
+    };
+    let mut out = vec![init; img.len()];
+    let mut window = VecDeque::new();
+    for y in 0..h {
+        for dy in -(radius as i64)..=radius as i64 {
+            let src = y as i64 + dy;
+            if src < 0 || src >= h as i64 {
+                continue;
+            }
+            let row = &img[src as usize * w..(src as usize + 1) * w];
+            let reach = Element::Disk.half_width(radius, dy.unsigned_abs() as u32) as usize;
+            let dst = &mut out[y * w..(y + 1) * w];
+            window.clear();
+            let mut next = 0;
+            for (x, px) in dst.iter_mut().enumerate() {
+                while next < w && next <= x + reach {
+                    while window.back().is_some_and(|&j| better(row[next], row[j])) {
+                        window.pop_back();
+                    }
+                    window.push_back(next);
+                    next += 1;
+                }
+                while window.front().is_some_and(|&j| j + reach < x) {
+                    window.pop_front();
+                }
+                let v = row[window[0]];
+                if better(v, *px) {
+                    *px = v;
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Heights of the ball over a square grid, trimmed at the rim the way
+/// ImageJ's `RollingBall` builds it.
+struct Ball {
+    half_width: i64,
+    z: Vec<f32>,
+}
+
+impl Ball {
+    fn new(radius: f64, arc_trim_percent: u32) -> Ball {
+        let radius = radius.max(1.0);
+        let trim = (arc_trim_percent as f64 * radius) as i64 / 100;
+        let half_width = (radius - trim as f64).round() as i64;
+        let side = 2 * half_width + 1;
+        let z = (0..side * side)
+            .map(|i| {
+                let (dx, dy) = (i % side - half_width, i / side - half_width);
+                let t = radius * radius - (dx * dx + dy * dy) as f64;
+                if t > 0.0 {
+                    t.sqrt() as f32
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        Ball { half_width, z }
+    }
+}
+
+fn rolling_ball(img: &[f32], width: u32, height: u32, radius: f64) -> Vec<f32> {
+    let (w, h) = (width as usize, height as usize);
+    let smoothed = mean_3x3(img, w, h);
+    let (shrink, trim) = match radius {
+        r if r <= 10.0 => (1, 24),
+        r if r <= 30.0 => (2, 24),
+        r if r <= 100.0 => (4, 32),
+        _ => (8, 40),
+    };
+    let ball = Ball::new(radius / shrink as f64, trim);
+    if shrink == 1 {
+        return roll(&smoothed, w, h, &ball);
+    }
+    // Each pixel of the shrunk image is the minimum of its block.
+    let (sw, sh) = (w.div_ceil(shrink), h.div_ceil(shrink));
+    let mut small = vec![f32::INFINITY; sw * sh];
+    for (i, &v) in smoothed.iter().enumerate() {
+        let cell = &mut small[(i / w / shrink) * sw + i % w / shrink];
+        *cell = cell.min(v);
+    }
+    enlarge(&roll(&small, sw, sh, &ball), sw, sh, w, h, shrink)
+}
+
+/// Mean over the 3x3 neighbourhood of each pixel, cropped at the border.
+fn mean_3x3(img: &[f32], w: usize, h: usize) -> Vec<f32> {
+    (0..w * h)
+        .map(|i| {
+            let (x, y) = (i % w, i / w);
+            let (mut sum, mut n) = (0.0, 0);
+            for yy in y.saturating_sub(1)..(y + 2).min(h) {
+                for xx in x.saturating_sub(1)..(x + 2).min(w) {
+                    sum += img[yy * w + xx];
+                    n += 1;
+                }
+            }
+            sum / n as f32
+        })
+        .collect()
+}
+
+/// The highest surface the ball reaches from below without poking through
+/// the image: a grayscale opening with the ball as structuring element.
+fn roll(img: &[f32], w: usize, h: usize, ball: &Ball) -> Vec<f32> {
+    let r = ball.half_width;
+    let side = (2 * r + 1) as usize;
+    let points = |x: usize, y: usize| {
+        (-r..=r)
+            .flat_map(move |dy| (-r..=r).map(move |dx| (dx, dy)))
+            .filter_map(move |(dx, dy)| {
+                let (xx, yy) = (x as i64 + dx, y as i64 + dy);
+                (xx >= 0 && yy >= 0 && xx < w as i64 && yy < h as i64).then(|| {
+                    (
+                        yy as usize * w + xx as usize,
+                        (dy + r) as usize * side + (dx + r) as usize,
+                    )
+                })
+            })
+    };
+    let lowest: Vec<f32> = (0..w * h)
+        .map(|i| {
+            points(i % w, i / w)
+                .map(|(p, b)| img[p] - ball.z[b])
+                .fold(f32::INFINITY, f32::min)
+        })
+        .collect();
+    let mut out = vec![f32::NEG_INFINITY; w * h];
+    for (i, &z) in lowest.iter().enumerate() {
+        for (p, b) in points(i % w, i / w) {
+            out[p] = out[p].max(z + ball.z[b]);
+        }
+    }
+    out
+}
+
+/// Bilinear interpolation of a shrunk image back to full size, with each
+/// shrunk pixel sitting at the center of its block.
+fn enlarge(small: &[f32], sw: usize, sh: usize, w: usize, h: usize, shrink: usize) -> Vec<f32> {
+    let axis = |n: usize, len: usize| -> Vec<(usize, usize, f32)> {
+        (0..n)
+            .map(|i| {
+                let t = ((i as f32 - (shrink - 1) as f32 / 2.0) / shrink as f32).max(0.0);
+                let i0 = (t as usize).min(len - 1);
+                let i1 = (i0 + 1).min(len - 1);
+                (i0, i1, (t - i0 as f32).min(1.0))
+            })
+            .collect()
+    };
+    let (xs, ys) = (axis(w, sw), axis(h, sh));
+    let mut out = Vec::with_capacity(w * h);
+    for &(y0, y1, fy) in &ys {
+        for &(x0, x1, fx) in &xs {
+            let top = small[y0 * sw + x0] * (1.0 - fx) + small[y0 * sw + x1] * fx;
+            let bottom = small[y1 * sw + x0] * (1.0 - fx) + small[y1 * sw + x1] * fx;
+            out.push(top * (1.0 - fy) + bottom * fy);
+        }
+    }
+    out
+}
@@ -0,0 +1,242 @@
+use crate::{contour, plane::Plane};
+use std::{f64, fmt, str};
+
+/// Which neighbours of a pixel count as touching it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Only the pixels sharing an edge.
+    #[default]
+    Four,
+    /// The pixels sharing an edge or a corner.
+    Eight,
+}
+
+impl Connectivity {
+    pub fn offsets(self) -> &'static [(i32, i32)] {
+        match self {
+            Connectivity::Four => &[(0, 1), (0, -1), (1, 0), (-1, 0)],
+            Connectivity::Eight => &[
+                (0, 1),
+                (0, -1),
+                (1, 0),
+                (-1, 0),
+                (1, 1),
+                (1, -1),
+                (-1, 1),
+                (-1, -1),
+            ],
+        }
+    }
+
+    /// The connectivity of the background around objects of this
+    /// connectivity.
+    pub fn dual(self) -> Connectivity {
+        match self {
+            Connectivity::Four => Connectivity::Eight,
+            Connectivity::Eight => Connectivity::Four,
+        }
+    }
+}
+
+impl str::FromStr for Connectivity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "4" => Ok(Connectivity::Four),
+            "8" => Ok(Connectivity::Eight),
+            _ => Err(format!("unsupported connectivity {s:?} (expected 4 or 8)")),
+        }
+    }
+}
+
+impl fmt::Display for Connectivity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Connectivity::Four => "4",
+            Connectivity::Eight => "8",
+        })
+    }
+}
+
+/// Axis-aligned bounds of a blob, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BoundingBox {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl BoundingBox {
+    fn of(pixels: &[(u32, u32)]) -> BoundingBox {
+        let (x0, y0, x1, y1) = pixels
+            .iter()
+            .fold((u32::MAX, u32::MAX, 0, 0), |(x0, y0, x1, y1), &(x, y)| {
+                (x0.min(x), y0.min(y), x1.max(x), y1.max(y))
+            });
+        BoundingBox {
+            x: x0,
+            y: y0,
+            width: x1 - x0 + 1,
+            height: y1 - y0 + 1,
+        }
+    }
+
+    pub(crate) fn contains(&self, (x, y): (u32, u32)) -> bool {
+        x >= self.x && y >= self.y && x - self.x < self.width && y - self.y < self.height
+    }
+}
+
+/// Membership bitmap of a blob over its bounding box.
+pub(crate) struct Footprint {
+    bbox: BoundingBox,
+    inside: Vec<bool>,
+}
+
+impl Footprint {
+    pub(crate) fn new(pixels: &[(u32, u32)], bbox: BoundingBox) -> Footprint {
+        let mut inside = vec![false; (bbox.width * bbox.height) as usize];
+        for &(x, y) in pixels {
+            inside[((y - bbox.y) * bbox.width + x - bbox.x) as usize] = true;
+        }
+        Footprint { bbox, inside }
+    }
+
+    pub(crate) fn contains(&self, pt: (u32, u32)) -> bool {
+        self.bbox.contains(pt)
+            && self.inside[((pt.1 - self.bbox.y) * self.bbox.width + pt.0 - self.bbox.x) as usize]
+    }
+
+    /// Iterates over the distinct pixels of the blob.
+    pub(crate) fn pixels(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        let bbox = self.bbox;
+        self.inside
+            .iter()
+            .enumerate()
+            .filter(|(_, &v)| v)
+            .map(move |(i, _)| {
+                (
+                    bbox.x + i as u32 % bbox.width,
+                    bbox.y + i as u32 / bbox.width,
+                )
+            })
+    }
+
+    /// Counts the neighbours of a pixel that lie outside the blob.
+    fn open_sides(&self, (x, y): (u32, u32), connectivity: Connectivity) -> usize {
+        connectivity
+            .offsets()
+            .iter()
+            .filter(|&&(dx, dy)| {
+                !self.contains((x.wrapping_add_signed(dx), y.wrapping_add_signed(dy)))
+            })
+            .count()
+    }
+}
+
+/// Intensity statistics of a blob's pixels in the original grayscale image,
+/// in its native units. NaN and infinite samples of floating point images
+/// are left out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Intensity {
+    pub mean: f64,
+    pub min: f32,
+    pub max: f32,
+}
+
+/// A connected group of pixels along with its measurements.
+#[derive(Clone, Debug)]
+pub struct Blob {
+    pub pixels: Vec<(u32, u32)>,
+    /// The connectivity the blob was labelled with, which also decides how
+    /// its outline and contour are traced.
+    pub connectivity: Connectivity,
+    pub centroid: (f64, f64),
+    pub area: usize,
+    pub bbox: BoundingBox,
+    /// Length of the outer contour with its corners cut diagonally, as
+    /// ImageJ measures traced outlines.
+    pub perimeter: f64,
+    /// `4π·area/perimeter²`, capped at 1.
+    pub circularity: f64,
+    /// Area relative to the area of the convex hull of the outer contour.
+    pub solidity: f64,
+    /// Eccentricity of the ellipse with the same second moments.
+    pub eccentricity: f64,
+    /// Major over minor axis of the ellipse with the same second moments.
+    pub aspect_ratio: f64,
+    /// `None` if none of the blob's samples is finite.
+    pub intensity: Option<Intensity>,
+}
+
+impl Blob {
+    /// Measures the pixels of a blob against the image they were found in.
+    pub fn new(pixels: Vec<(u32, u32)>, img: &Plane, connectivity: Connectivity) -> Blob {
+        let (sx, sy) = pixels
+            .iter()
+            .copied()
+            .map(|(x, y)| (x as f64, y as f64))
+            .fold((0.0, 0.0), |(ax, ay), (bx, by)| (ax + bx, ay + by));
+        let centroid = (sx / pixels.len() as f64, sy / pixels.len() as f64);
+        let bbox = BoundingBox::of(&pixels);
+        let footprint = Footprint::new(&pixels, bbox);
+        let exterior = contour::trace(&footprint, connectivity).exterior;
+        let area = pixels.len() as f64;
+        let perimeter = contour::traced_perimeter(&exterior);
+        let circularity = if perimeter > 0.0 {
+            (4.0 * f64::consts::PI * area / (perimeter * perimeter)).min(1.0)
+        } else {
+            0.0
+        };
+        let solidity = area / contour::hull_area(&exterior);
+        // Second central moments of the pixel squares; each contributes its
+        // own 1/12 on top of its center's offset from the centroid.
+        let (mxx, myy, mxy) = pixels
+            .iter()
+            .fold((0.0, 0.0, 0.0), |(xx, yy, xy), &(x, y)| {
+                let (dx, dy) = (x as f64 - centroid.0, y as f64 - centroid.1);
+                (xx + dx * dx, yy + dy * dy, xy + dx * dy)
+            });
+        let (mxx, myy, mxy) = (mxx / area + 1.0 / 12.0, myy / area + 1.0 / 12.0, mxy / area);
+        let spread = ((mxx - myy) * (mxx - myy) / 4.0 + mxy * mxy).sqrt();
+        let (major, minor) = ((mxx + myy) / 2.0 + spread, (mxx + myy) / 2.0 - spread);
+        let (count, sum, min, max) = pixels
+            .iter()
+            .map(|&pt| img.image[pt].0[0])
+            .filter(|v| v.is_finite())
+            .fold(
+                (0, 0.0, f32::INFINITY, f32::NEG_INFINITY),
+                |(count, sum, min, max): (usize, f64, f32, f32), v| {
+                    (count + 1, sum + v as f64, min.min(v), max.max(v))
+                },
+            );
+        Blob {
+            connectivity,
+            area: pixels.len(),
+            centroid,
+            bbox,
+            perimeter,
+            circularity,
+            solidity,
+            eccentricity: (1.0 - minor / major).sqrt(),
+            aspect_ratio: (major / minor).sqrt(),
+            intensity: (count > 0).then(|| Intensity {
+                mean: sum / count as f64,
+                min,
+                max,
+            }),
+            pixels,
+        }
+    }
+
+    /// Returns the pixels of the blob that touch its surroundings, where the
+    /// surroundings use the dual of the blob's connectivity.
+    pub fn outline(&self) -> Vec<(u32, u32)> {
+        let footprint = Footprint::new(&self.pixels, self.bbox);
+        footprint
+            .pixels()
+            .filter(|&pt| footprint.open_sides(pt, self.connectivity.dual()) > 0)
+            .collect()
+    }
+}
@@ -0,0 +1,132 @@
+use crate::Connectivity;
+
+/// A horizontal stretch of foreground pixels `x0..x1` on row `y`.
+struct Run {
+    y: u32,
+    x0: u32,
+    x1: u32,
+    label: u32,
+}
+
+fn find(parent: &mut [u32], mut label: u32) -> u32 {
+    while parent[label as usize] != label {
+        let grandparent = parent[parent[label as usize] as usize];
+        parent[label as usize] = grandparent;
+        label = grandparent;
+    }
+    label
+}
+
+fn union(parent: &mut [u32], a: u32, b: u32) -> u32 {
+    let (a, b) = (find(parent, a), find(parent, b));
+    let (lo, hi) = (a.min(b), a.max(b));
+    parent[hi as usize] = lo;
+    lo
+}
+
+/// Hysteresis labelling: returns every connected component of `grows` that
+/// contains at least one pixel of `seeds`, as a list of pixel coordinates.
+/// Seed pixels always count as grow pixels, which only matches a flood fill
+/// from the seeds if they lie within the grow mask; see
+/// [`SegmentationParams::validate`](crate::SegmentationParams::validate).
+///
+/// Components are ordered by their first seed pixel in raster order and list
+/// their pixels in raster order.
+///
+/// This is a two-pass scanline labeller: the first pass splits every row into
+/// runs of foreground pixels and merges the labels of runs that touch a run
+/// on the previous row in a union-find forest, the second resolves the labels
+/// and gathers the pixels run by run.
+pub fn hysteresis(
+    width: u32,
+    height: u32,
+    seeds: &[bool],
+    grows: &[bool],
+    connectivity: Connectivity,
+) -> Vec<Vec<(u32, u32)>> {
+    let w = width as usize;
+    // Runs on the previous row reach one pixel further with 8-connectivity.
+    let reach = match connectivity {
+        Connectivity::Four => 0,
+        Connectivity::Eight => 1,
+    };
+    let mut runs: Vec<Run> = Vec::new();
+    let mut parent = Vec::new();
+    let mut prev = 0..0;
+    for y in 0..height {
+        let row_start = runs.len();
+        let row = y as usize * w;
+        let mut x = 0;
+        while x < width {
+            if !grows[row + x as usize] && !seeds[row + x as usize] {
+                x += 1;
+                continue;
+            }
+            let x0 = x;
+            while x < width && (grows[row + x as usize] || seeds[row + x as usize]) {
+                x += 1;
+            }
+            let mut label = u32::MAX;
+            // Runs within a row are sorted, so skip the ones on the previous
+            // row that end before this one starts.
+            while prev.start < prev.end && runs[prev.start].x1 + reach <= x0 {
+                prev.start += 1;
+            }
+            let mut i = prev.start;
+            while i < prev.end && runs[i].x0 < x + reach {
+                label = if label == u32::MAX {
+                    find(&mut parent, runs[i].label)
+                } else {
+                    union(&mut parent, label, runs[i].label)
+                };
+                i += 1;
+            }
+            // The last overlapping run may touch the next run on this row too.
+            prev.start = i.saturating_sub(1).max(prev.start);
+            if label == u32::MAX {
+                label = parent.len() as u32;
+                parent.push(label);
+            }
+            runs.push(Run {
+                y,
+                x0,
+                x1: x,
+                label,
+            });
+        }
+        prev = row_start..runs.len();
+    }
+    let mut first_seed = vec![usize::MAX; parent.len()];
+    let mut sizes = vec![0; parent.len()];
+    for run in &mut runs {
+        run.label = find(&mut parent, run.label);
+        let root = run.label as usize;
+        sizes[root] += (run.x1 - run.x0) as usize;
+        if first_seed[root] == usize::MAX {
+            let row = run.y as usize * w;
+            if let Some(x) = (run.x0..run.x1).find(|&x| seeds[row + x as usize]) {
+                first_seed[root] = row + x as usize;
+            }
+        }
+    }
+    let mut roots: Vec<_> = (0..parent.len())
+        .filter(|&r| first_seed[r] != usize::MAX)
+        .collect();
+    roots.sort_unstable_by_key(|&r| first_seed[r]);
+    let mut slots = vec![usize::MAX; parent.len()];
+    let mut components: Vec<_> = roots
+        .iter()
+        .enumerate()
+        .map(|(slot, &r)| {
+            slots[r] = slot;
+            Vec::with_capacity(sizes[r])
+        })
+        .collect();
+    for run in &runs {
+        let slot = slots[run.label as usize];
+        if slot != usize::MAX {
+            components[slot].extend((run.x0..run.x1).map(|x| (x, run.y)));
+        }
+    }
+    components
+}
@@ -0,0 +1,211 @@
+use crate::plane::{Depth, Plane};
+use image::DynamicImage;
+use std::{fmt, str};
+
+/// Stain vectors of hematoxylin, eosin and DAB in optical density, as
+/// measured by Ruifrok and Johnston.
+const STAINS: [[f64; 3]; 3] = [[0.65, 0.70, 0.29], [0.07, 0.99, 0.11], [0.27, 0.57, 0.78]];
+
+/// Which quantity of a color image is segmented.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Channel {
+    /// Luminance with the Rec. 709 weights of the `image` crate.
+    #[default]
+    Luma,
+    Red,
+    Green,
+    Blue,
+    /// Opacity, at full scale for images without an alpha channel.
+    Alpha,
+    /// Hue angle with a full turn spanning the full scale, starting at red.
+    /// Gray pixels have hue 0.
+    Hue,
+    /// `(max - min) / max` of red, green and blue, as a fraction of full
+    /// scale.
+    Saturation,
+    /// The largest of red, green and blue.
+    Value,
+    /// Weighted sum of red, green and blue.
+    Weights([f64; 3]),
+    /// The hematoxylin stain separated by color deconvolution and rendered
+    /// as the light it alone would transmit, so that stained areas are dark.
+    Hematoxylin,
+    /// The eosin stain, separated like [`Channel::Hematoxylin`].
+    Eosin,
+    /// The DAB stain, separated like [`Channel::Hematoxylin`].
+    Dab,
+}
+
+impl Channel {
+    /// Extracts the channel from a decoded image, keeping 16 bit and
+    /// floating point samples at full precision.
+    pub fn extract(self, img: &DynamicImage) -> Plane {
+        if self == Channel::Luma {
+            return Plane::from_dynamic(img);
+        }
+        let (depth, pixels): (_, Vec<[f32; 4]>) = match img {
+            DynamicImage::ImageLuma16(_)
+            | DynamicImage::ImageLumaA16(_)
+            | DynamicImage::ImageRgb16(_)
+            | DynamicImage::ImageRgba16(_) => (
+                Depth::U16,
+                img.to_rgba16()
+                    .pixels()
+                    .map(|px| px.0.map(f32::from))
+                    .collect(),
+            ),
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => (
+                Depth::F32,
+                img.to_rgba32f().pixels().map(|px| px.0).collect(),
+            ),
+            _ => (
+                Depth::U8,
+                img.to_rgba8()
+                    .pixels()
+                    .map(|px| px.0.map(f32::from))
+                    .collect(),
+            ),
+        };
+        self.convert(depth, img.width(), img.height(), pixels)
+    }
+
+    /// Extracts the channel from a grayscale plane, reading every sample as
+    /// an opaque gray pixel.
+    pub fn extract_gray(self, plane: Plane) -> Plane {
+        if self == Channel::Luma {
+            return plane;
+        }
+        let full = plane.depth.full_scale() as f32;
+        let pixels = plane.samples().iter().map(|&v| [v, v, v, full]).collect();
+        self.convert(plane.depth, plane.width(), plane.height(), pixels)
+    }
+
+    fn convert(self, depth: Depth, width: u32, height: u32, pixels: Vec<[f32; 4]>) -> Plane {
+        let full = depth.full_scale();
+        let unmix = match self {
+            Channel::Hematoxylin | Channel::Eosin | Channel::Dab => {
+                let row = |s: [f64; 3]| {
+                    let norm = s.iter().map(|v| v * v).sum::<f64>().sqrt();
+                    s.map(|v| v / norm)
+                };
+                invert(STAINS.map(row))
+            }
+            _ => [[0.0; 3]; 3],
+        };
+        let samples = pixels
+            .into_iter()
+            .map(|[r, g, b, a]| {
+                let (r, g, b) = (r as f64, g as f64, b as f64);
+                let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+                let v = match self {
+                    Channel::Luma => unreachable!("luma is converted by the image crate"),
+                    Channel::Red => r,
+                    Channel::Green => g,
+                    Channel::Blue => b,
+                    Channel::Alpha => a as f64,
+                    Channel::Hue if max == min => 0.0,
+                    Channel::Hue => {
+                        let sector = if max == r {
+                            ((g - b) / (max - min)).rem_euclid(6.0)
+                        } else if max == g {
+                            (b - r) / (max - min) + 2.0
+                        } else {
+                            (r - g) / (max - min) + 4.0
+                        };
+                        sector / 6.0 * full
+                    }
+                    Channel::Saturation if max <= 0.0 => 0.0,
+                    Channel::Saturation => (max - min) / max * full,
+                    Channel::Value => max,
+                    Channel::Weights([wr, wg, wb]) => wr * r + wg * g + wb * b,
+                    Channel::Hematoxylin | Channel::Eosin | Channel::Dab => {
+                        let stain = match self {
+                            Channel::Hematoxylin => 0,
+                            Channel::Eosin => 1,
+                            _ => 2,
+                        };
+                        let density: f64 = [r, g, b]
+                            .iter()
+                            .zip(&unmix)
+                            .map(|(&v, inverse)| -(v / full).max(1e-6).log10() * inverse[stain])
+                            .sum();
+                        full * 10f64.powf(-density.max(0.0))
+                    }
+                };
+                match depth {
+                    Depth::F32 => v as f32,
+                    Depth::U8 | Depth::U16 => v.round().clamp(0.0, full) as f32,
+                }
+            })
+            .collect();
+        Plane::new(depth, width, height, samples).expect("one sample per pixel")
+    }
+}
+
+/// Inverse of a 3x3 matrix by cofactors.
+fn invert(m: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let cofactor = |r: usize, c: usize| {
+        let (r0, r1, c0, c1) = ((r + 1) % 3, (r + 2) % 3, (c + 1) % 3, (c + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let det: f64 = (0..3).map(|c| m[0][c] * cofactor(0, c)).sum();
+    let mut out = [[0.0; 3]; 3];
+    for (r, row) in out.iter_mut().enumerate() {
+        for (c, v) in row.iter_mut().enumerate() {
+            *v = cofactor(c, r) / det;
+        }
+    }
+    out
+}
+
+impl str::FromStr for Channel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "luma" => Ok(Channel::Luma),
+            "r" => Ok(Channel::Red),
+            "g" => Ok(Channel::Green),
+            "b" => Ok(Channel::Blue),
+            "a" => Ok(Channel::Alpha),
+            "hue" => Ok(Channel::Hue),
+            "saturation" => Ok(Channel::Saturation),
+            "value" => Ok(Channel::Value),
+            "hematoxylin" => Ok(Channel::Hematoxylin),
+            "eosin" => Ok(Channel::Eosin),
+            "dab" => Ok(Channel::Dab),
+            _ => match s.strip_prefix("weights:").map(|w| {
+                w.split(':')
+                    .map(|v| v.parse::<f64>().ok().filter(|v| v.is_finite()))
+                    .collect::<Option<Vec<_>>>()
+            }) {
+                Some(Some(w)) if w.len() == 3 => Ok(Channel::Weights([w[0], w[1], w[2]])),
+                Some(_) => Err(format!(
+                    "invalid channel weights {s:?} (expected weights:<r>:<g>:<b>)"
+                )),
+                None => Err(format!(
+                    "unknown channel {s:?} (expected r, g, b, a, luma, hue, saturation, value, weights:<r>:<g>:<b>, hematoxylin, eosin or dab)"
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Channel::Luma => f.write_str("luma"),
+            Channel::Red => f.write_str("r"),
+            Channel::Green => f.write_str("g"),
+            Channel::Blue => f.write_str("b"),
+            Channel::Alpha => f.write_str("a"),
+            Channel::Hue => f.write_str("hue"),
+            Channel::Saturation => f.write_str("saturation"),
+            Channel::Value => f.write_str("value"),
+            Channel::Weights([r, g, b]) => write!(f, "weights:{r}:{g}:{b}"),
+            Channel::Hematoxylin => f.write_str("hematoxylin"),
+            Channel::Eosin => f.write_str("eosin"),
+            Channel::Dab => f.write_str("dab"),
+        }
+    }
+}
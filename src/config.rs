@@ -0,0 +1,128 @@
+//! TOML config files holding command line settings.
+//!
+//! Keys are the long names of the command line flags, with dashes or
+//! underscores. Top-level keys apply to every run and the tables under
+//! `[profiles.<name>]` add to or replace them when that profile is chosen:
+//!
+//! ```toml
+//! threshold = "auto:otsu"
+//! csv = "combined"
+//!
+//! [profiles.brightfield_10x]
+//! max_black = 50
+//! background = "rolling-ball:40"
+//! overlay = true
+//! ```
+
+use clap::{parser::ValueSource, ArgAction, ArgMatches, Command};
+use std::{error, ffi::OsString, fs, path};
+use toml::{Table, Value};
+
+/// Settings that only make sense on the command line.
+const CLI_ONLY: &[&str] = &["config", "profile", "print_config"];
+
+/// Reads a config file and returns its settings, and those of `profile`, as
+/// command line arguments to add to `cli`. Settings that `cli` already gives
+/// are left out, so that the command line wins.
+pub fn arguments(
+    command: &Command,
+    cli: &ArgMatches,
+    path: &path::Path,
+    profile: Option<&str>,
+) -> Result<Vec<OsString>, Box<dyn Send + Sync + error::Error>> {
+    let file: Table = fs::read_to_string(path)?
+        .parse()
+        .map_err(|err| format!("Invalid config file {}: {err}", path.display()))?;
+    let mut settings = Table::new();
+    let mut profiles = Table::new();
+    for (key, value) in file {
+        match (key.as_str(), value) {
+            ("profiles", Value::Table(table)) => profiles = table,
+            (_, value) => {
+                settings.insert(key.replace('-', "_"), value);
+            }
+        }
+    }
+    if let Some(name) = profile {
+        match profiles.remove(name) {
+            Some(Value::Table(table)) => settings.extend(
+                table
+                    .into_iter()
+                    .map(|(key, value)| (key.replace('-', "_"), value)),
+            ),
+            Some(_) => return Err(format!("Profile {name:?} is not a table").into()),
+            None => {
+                return Err(format!("No profile {name:?} in config file {}", path.display()).into())
+            }
+        }
+    }
+    let mut args = Vec::new();
+    for (id, value) in settings {
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == id.as_str() && !CLI_ONLY.contains(&id.as_str()))
+            .ok_or_else(|| format!("Unknown setting {id:?} in config file {}", path.display()))?;
+        if cli.value_source(&id) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let flag = format!("--{}", arg.get_long().unwrap_or(&id));
+        match value {
+            Value::Array(values) => {
+                for value in values {
+                    args.push(format!("{flag}={}", scalar(&id, value)?).into());
+                }
+            }
+            value => args.push(format!("{flag}={}", scalar(&id, value)?).into()),
+        }
+    }
+    Ok(args)
+}
+
+fn scalar(key: &str, value: Value) -> Result<String, Box<dyn Send + Sync + error::Error>> {
+    match value {
+        Value::String(s) => Ok(s),
+        Value::Integer(v) => Ok(v.to_string()),
+        Value::Float(v) => Ok(v.to_string()),
+        Value::Boolean(v) => Ok(v.to_string()),
+        _ => Err(format!("Setting {key:?} must be a string, number or boolean").into()),
+    }
+}
+
+/// Formats the resolved settings, defaults included, as a config file that
+/// reproduces them.
+pub fn print(command: &Command, matches: &ArgMatches) -> String {
+    let mut out = String::new();
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+        let action = arg.get_action();
+        if CLI_ONLY.contains(&id) || !matches!(action, ArgAction::Set | ArgAction::Append) {
+            continue;
+        }
+        let Some(values) = matches.get_raw(id) else {
+            continue;
+        };
+        let values: Vec<_> = values.map(|v| value(&v.to_string_lossy())).collect();
+        let value = match action {
+            ArgAction::Append => format!("[{}]", values.join(", ")),
+            _ => values.join(" "),
+        };
+        out.push_str(&format!("{id} = {value}\n"));
+    }
+    out
+}
+
+/// Writes a raw flag value unquoted if TOML reads it back as the same
+/// number or boolean, and as a string otherwise.
+fn value(raw: &str) -> String {
+    let number = raw
+        .parse::<i64>()
+        .map(Value::Integer)
+        .ok()
+        .or_else(|| raw.parse::<f64>().ok().map(Value::Float))
+        .or_else(|| raw.parse::<bool>().ok().map(Value::Boolean))
+        .map(|v| v.to_string());
+    match number {
+        Some(v) if v == raw => v,
+        _ => Value::String(raw.to_owned()).to_string(),
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{blob::Footprint, Blob, Connectivity};
+use std::{collections::HashMap, f64};
+
+/// The boundary of a blob as a polygon over pixel corners, so that it covers
+/// exactly the blob's pixels. Rings are closed implicitly, contain only the
+/// vertices where the boundary changes direction and follow the GeoJSON
+/// winding order: counterclockwise for the exterior, clockwise for holes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Contour {
+    pub exterior: Vec<(u32, u32)>,
+    pub holes: Vec<Vec<(u32, u32)>>,
+}
+
+struct Edge {
+    from: (u32, u32),
+    to: (u32, u32),
+    pixel: (u32, u32),
+}
+
+impl Blob {
+    /// Traces the outer boundary and the holes of the blob.
+    pub fn contour(&self) -> Contour {
+        trace(&Footprint::new(&self.pixels, self.bbox), self.connectivity)
+    }
+}
+
+pub(crate) fn trace(footprint: &Footprint, connectivity: Connectivity) -> Contour {
+    // Every pixel side facing the outside becomes an edge running
+    // clockwise around that pixel on screen.
+    let mut edges = Vec::new();
+    for (x, y) in footprint.pixels() {
+        let sides = [
+            ((x, y.wrapping_sub(1)), (x, y), (x + 1, y)),
+            ((x + 1, y), (x + 1, y), (x + 1, y + 1)),
+            ((x, y + 1), (x + 1, y + 1), (x, y + 1)),
+            ((x.wrapping_sub(1), y), (x, y + 1), (x, y)),
+        ];
+        for (neighbour, from, to) in sides {
+            if !footprint.contains(neighbour) {
+                edges.push(Edge {
+                    from,
+                    to,
+                    pixel: (x, y),
+                });
+            }
+        }
+    }
+    let mut starts: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+    for (i, edge) in edges.iter().enumerate() {
+        starts.entry(edge.from).or_default().push(i);
+    }
+    let mut used = vec![false; edges.len()];
+    let mut rings = Vec::new();
+    for first in 0..edges.len() {
+        if used[first] {
+            continue;
+        }
+        let mut ring = Vec::new();
+        let mut cur = first;
+        while !used[cur] {
+            used[cur] = true;
+            ring.push(edges[cur].from);
+            let next = &starts[&edges[cur].to];
+            // Two edges leave a corner only where the blob touches itself
+            // diagonally. With 4-connectivity keep walking around the same
+            // pixel so that the diagonal neighbours stay apart, with
+            // 8-connectivity cross over to join them.
+            let same_pixel = connectivity == Connectivity::Four;
+            cur = *next
+                .iter()
+                .find(|&&i| next.len() == 1 || (edges[i].pixel == edges[cur].pixel) == same_pixel)
+                .unwrap_or(&next[0]);
+        }
+        rings.push(simplify(ring));
+    }
+    // A blob traced with the connectivity it was labelled with has exactly
+    // one counterclockwise ring; everything else bounds a hole.
+    let outer = (0..rings.len())
+        .max_by_key(|&i| signed_area(&rings[i]))
+        .unwrap_or(0);
+    let exterior = rings.swap_remove(outer);
+    let holes = rings;
+    Contour { exterior, holes }
+}
+
+/// The length of a traced ring with its corners cut diagonally, as ImageJ
+/// measures the perimeter of traced selections.
+pub(crate) fn traced_perimeter(ring: &[(u32, u32)]) -> f64 {
+    let n = ring.len();
+    let side = |i: usize| {
+        let (a, b) = (ring[(i + n - 1) % n], ring[i % n]);
+        (a.0.abs_diff(b.0), a.1.abs_diff(b.1))
+    };
+    let (mut length, mut corners, mut corner) = (0, 0, false);
+    for i in 0..n {
+        let (dx, dy) = side(i);
+        length += dx + dy;
+        if dx + dy > 1 || !corner {
+            corner = true;
+            corners += 1;
+        } else {
+            corner = false;
+        }
+    }
+    length as f64 - corners as f64 * (2.0 - f64::consts::SQRT_2)
+}
+
+/// The area of the convex hull of a ring.
+pub(crate) fn hull_area(ring: &[(u32, u32)]) -> f64 {
+    let mut points = ring.to_vec();
+    points.sort_unstable();
+    points.dedup();
+    let cross = |o: (u32, u32), a: (u32, u32), b: (u32, u32)| {
+        (a.0 as i64 - o.0 as i64) * (b.1 as i64 - o.1 as i64)
+            - (a.1 as i64 - o.1 as i64) * (b.0 as i64 - o.0 as i64)
+    };
+    // Andrew's monotone chain.
+    let mut hull: Vec<(u32, u32)> = Vec::with_capacity(points.len() + 1);
+    for pass in 0..2 {
+        let start = hull.len();
+        for &p in points.iter() {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0
+            {
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        hull.pop();
+        if pass == 0 {
+            points.reverse();
+        }
+    }
+    signed_area(&hull).abs() as f64 / 2.0
+}
+
+/// Drops the vertices that lie on a straight run of edges.
+fn simplify(ring: Vec<(u32, u32)>) -> Vec<(u32, u32)> {
+    let n = ring.len();
+    (0..n)
+        .filter(|&i| {
+            let (a, b, c) = (ring[(i + n - 1) % n], ring[i], ring[(i + 1) % n]);
+            (b.0 as i64 - a.0 as i64) * (c.1 as i64 - b.1 as i64)
+                != (b.1 as i64 - a.1 as i64) * (c.0 as i64 - b.0 as i64)
+        })
+        .map(|i| ring[i])
+        .collect()
+}
+
+/// Twice the signed area enclosed by a ring, positive when counterclockwise
+/// with the y axis pointing up.
+fn signed_area(ring: &[(u32, u32)]) -> i64 {
+    let n = ring.len();
+    (0..n)
+        .map(|i| {
+            let (a, b) = (ring[i], ring[(i + 1) % n]);
+            a.0 as i64 * b.1 as i64 - b.0 as i64 * a.1 as i64
+        })
+        .sum()
+}
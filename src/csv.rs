@@ -0,0 +1,166 @@
+use crate::{plane::format_level, Levels, Rejection, Segmentation};
+use std::{io, path, time};
+
+/// Column names of the per-blob measurement table.
+pub const HEADER: &str = "image,frame,blob_id,centroid_x,centroid_y,area,bbox_x,bbox_y,bbox_width,bbox_height,perimeter,circularity,solidity,eccentricity,aspect_ratio,mean_intensity,min_intensity,max_intensity";
+
+/// Column names of the per-image summary table.
+pub const SUMMARY_HEADER: &str = "image,frame,width,height,polarity,threshold,seed_level,grow_level,accepted,rejected_too_small,rejected_too_large,rejected_not_round,rejected_circularity,rejected_solidity,rejected_eccentricity,rejected_aspect_ratio,total_area,mean_area,median_area,area_fraction,segmentation_ms";
+
+/// Column names of the track table.
+pub const TRACKS_HEADER: &str = "track_id,frame,image,blob_id,x,y";
+
+/// Quotes a field if it would otherwise break the row apart.
+pub fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Writes one row per accepted blob, without a header. `frame` is the
+/// 0-based page of the image in its file; single images are frame 0.
+pub fn write_rows(
+    out: &mut impl io::Write,
+    image: &path::Path,
+    frame: usize,
+    segmentation: &Segmentation,
+) -> io::Result<()> {
+    let image = escape(&image.display().to_string());
+    for (i, blob) in segmentation.blobs.iter().enumerate() {
+        let intensity = match blob.intensity {
+            Some(intensity) => (
+                segmentation.depth.format_mean(intensity.mean),
+                intensity.min.to_string(),
+                intensity.max.to_string(),
+            ),
+            None => Default::default(),
+        };
+        writeln!(
+            out,
+            "{},{},{},{:.3},{:.3},{},{},{},{},{},{:.3},{:.4},{:.4},{:.4},{:.4},{},{},{}",
+            image,
+            frame,
+            i + 1,
+            blob.centroid.0,
+            blob.centroid.1,
+            blob.area,
+            blob.bbox.x,
+            blob.bbox.y,
+            blob.bbox.width,
+            blob.bbox.height,
+            blob.perimeter,
+            blob.circularity,
+            blob.solidity,
+            blob.eccentricity,
+            blob.aspect_ratio,
+            intensity.0,
+            intensity.1,
+            intensity.2
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes one row per tracked blob, without a header, ordered by track and
+/// frame. `frames` holds the image and the accepted blob centroids of every
+/// frame of the sequence, and `ids` the track ID of every centroid as
+/// returned by [`track::link`](crate::track::link).
+pub fn write_tracks(
+    out: &mut impl io::Write,
+    frames: &[(&path::Path, &[(f64, f64)])],
+    ids: &[Vec<usize>],
+) -> io::Result<()> {
+    let mut rows: Vec<(usize, usize, usize)> = ids
+        .iter()
+        .enumerate()
+        .flat_map(|(frame, ids)| ids.iter().enumerate().map(move |(i, &id)| (id, frame, i)))
+        .collect();
+    rows.sort_unstable();
+    for (id, frame, i) in rows {
+        let (image, centroids) = frames[frame];
+        let (x, y) = centroids[i];
+        writeln!(
+            out,
+            "{},{},{},{},{:.3},{:.3}",
+            id,
+            frame,
+            escape(&image.display().to_string()),
+            i + 1,
+            x,
+            y
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the summary row of one segmented image, without a header. The
+/// threshold is the automatically selected one, or the mean of the local
+/// thresholds, and is empty for fixed levels. Areas are those of the accepted
+/// blobs, and `elapsed` is the time the segmentation took.
+pub fn write_summary(
+    out: &mut impl io::Write,
+    image: &path::Path,
+    frame: usize,
+    segmentation: &Segmentation,
+    elapsed: time::Duration,
+) -> io::Result<()> {
+    let (threshold, seed, grow) = match segmentation.levels {
+        Levels::Global {
+            threshold,
+            seed,
+            grow,
+        } => (
+            threshold.map(format_level),
+            Some(format_level(seed)),
+            Some(format_level(grow)),
+        ),
+        Levels::Local { mean } => (Some(format_level(mean)), None, None),
+    };
+    let rejected: Vec<String> = Rejection::ALL
+        .iter()
+        .map(|&reason| {
+            let n = segmentation
+                .rejected
+                .iter()
+                .filter(|(_, r)| *r == reason)
+                .count();
+            n.to_string()
+        })
+        .collect();
+    let mut areas: Vec<usize> = segmentation.blobs.iter().map(|blob| blob.area).collect();
+    areas.sort_unstable();
+    let total: usize = areas.iter().sum();
+    let (mean, median) = match areas.len() {
+        0 => (String::new(), String::new()),
+        n => (
+            format!("{:.3}", total as f64 / n as f64),
+            format!("{:.1}", (areas[(n - 1) / 2] + areas[n / 2]) as f64 / 2.0),
+        ),
+    };
+    let pixels = segmentation.width as f64 * segmentation.height as f64;
+    writeln!(
+        out,
+        "{},{},{},{},{},{},{},{},{},{},{},{},{},{:.6},{:.3}",
+        escape(&image.display().to_string()),
+        frame,
+        segmentation.width,
+        segmentation.height,
+        segmentation.polarity,
+        threshold.unwrap_or_default(),
+        seed.unwrap_or_default(),
+        grow.unwrap_or_default(),
+        segmentation.blobs.len(),
+        rejected.join(","),
+        total,
+        mean,
+        median,
+        if pixels > 0.0 {
+            total as f64 / pixels
+        } else {
+            0.0
+        },
+        elapsed.as_secs_f64() * 1000.0
+    )
+}
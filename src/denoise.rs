@@ -0,0 +1,177 @@
+use crate::plane::{Plane, Units};
+use std::{borrow::Cow, fmt, str};
+
+/// Smoothing applied to the grayscale image before it is thresholded.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Denoise {
+    #[default]
+    None,
+    /// Gaussian blur with the given standard deviation in pixels.
+    Gaussian { sigma: f64 },
+    /// Median over a square window reaching `radius` pixels from its center.
+    Median { radius: u32 },
+    /// Edge-preserving average weighted by distance (`sigma_spatial`, in
+    /// pixels) and by intensity difference (`sigma_range`, in levels of the
+    /// configured units).
+    Bilateral {
+        sigma_spatial: f64,
+        sigma_range: f64,
+    },
+}
+
+impl Denoise {
+    /// Returns the smoothed image, or the input itself when smoothing is
+    /// off.
+    pub fn apply(self, img: &Plane, units: Units) -> Cow<'_, Plane> {
+        let samples = match self {
+            Denoise::None => return Cow::Borrowed(img),
+            Denoise::Gaussian { sigma } => gaussian(img, sigma),
+            Denoise::Median { radius } => median(img, radius),
+            Denoise::Bilateral {
+                sigma_spatial,
+                sigma_range,
+            } => bilateral(img, sigma_spatial, units.to_native(sigma_range, img.depth)),
+        };
+        Cow::Owned(
+            Plane::new(img.depth, img.width(), img.height(), samples)
+                .expect("filters keep the dimensions"),
+        )
+    }
+}
+
+impl str::FromStr for Denoise {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = |v: &str| {
+            v.parse::<f64>()
+                .ok()
+                .filter(|v| v.is_finite() && *v > 0.0)
+                .ok_or_else(|| {
+                    format!("invalid filter parameter {v:?} (expected a positive number)")
+                })
+        };
+        match s.split_once(':') {
+            None if s == "none" => Ok(Denoise::None),
+            Some(("gaussian", sigma)) => Ok(Denoise::Gaussian {
+                sigma: number(sigma)?,
+            }),
+            Some(("median", radius)) => Ok(Denoise::Median {
+                radius: radius
+                    .parse()
+                    .map_err(|_| format!("invalid median radius {radius:?}"))?,
+            }),
+            Some(("bilateral", sigmas)) => {
+                let (spatial, range) = sigmas.split_once(':').ok_or_else(|| {
+                    format!("invalid bilateral sigmas {sigmas:?} (expected <spatial>:<range>)")
+                })?;
+                Ok(Denoise::Bilateral {
+                    sigma_spatial: number(spatial)?,
+                    sigma_range: number(range)?,
+                })
+            }
+            _ => Err(format!(
+                "unknown filter {s:?} (expected none, gaussian:<sigma>, median:<radius> or bilateral:<spatial>:<range>)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Denoise {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Denoise::None => f.write_str("none"),
+            Denoise::Gaussian { sigma } => write!(f, "gaussian:{sigma}"),
+            Denoise::Median { radius } => write!(f, "median:{radius}"),
+            Denoise::Bilateral {
+                sigma_spatial,
+                sigma_range,
+            } => write!(f, "bilateral:{sigma_spatial}:{sigma_range}"),
+        }
+    }
+}
+
+/// Separable Gaussian blur over a kernel reaching three standard
+/// deviations, renormalised where it is cropped at the border.
+fn gaussian(img: &Plane, sigma: f64) -> Vec<f32> {
+    let (w, h) = (img.width() as usize, img.height() as usize);
+    let radius = (3.0 * sigma).ceil() as usize;
+    let kernel: Vec<f64> = (0..=2 * radius)
+        .map(|i| {
+            let d = i as f64 - radius as f64;
+            (-d * d / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let pass = |src: &[f32], len: usize, stride: usize, lines: usize, step: usize| {
+        let mut out = vec![0.0; src.len()];
+        for line in 0..lines {
+            for i in 0..len {
+                let (lo, hi) = (i.saturating_sub(radius), (i + radius + 1).min(len));
+                let (mut sum, mut norm) = (0.0, 0.0);
+                for j in lo..hi {
+                    let k = kernel[j + radius - i];
+                    sum += k * src[line * step + j * stride] as f64;
+                    norm += k;
+                }
+                out[line * step + i * stride] = (sum / norm) as f32;
+            }
+        }
+        out
+    };
+    let rows = pass(img.samples(), w, 1, h, w);
+    pass(&rows, h, w, w, 1)
+}
+
+/// Median over a square window cropped at the image border.
+fn median(img: &Plane, radius: u32) -> Vec<f32> {
+    let (w, h) = (img.width() as usize, img.height() as usize);
+    let r = radius as usize;
+    let samples = img.samples();
+    let mut window = Vec::with_capacity((2 * r + 1) * (2 * r + 1));
+    (0..w * h)
+        .map(|i| {
+            let (x, y) = (i % w, i / w);
+            window.clear();
+            for yy in y.saturating_sub(r)..(y + r + 1).min(h) {
+                window.extend_from_slice(
+                    &samples[yy * w + x.saturating_sub(r)..yy * w + (x + r + 1).min(w)],
+                );
+            }
+            // Windows with an even count take the lower middle sample.
+            let mid = (window.len() - 1) / 2;
+            *window.select_nth_unstable_by(mid, f32::total_cmp).1
+        })
+        .collect()
+}
+
+fn bilateral(img: &Plane, sigma_spatial: f64, sigma_range: f64) -> Vec<f32> {
+    let (w, h) = (img.width() as usize, img.height() as usize);
+    let samples = img.samples();
+    let radius = (2.0 * sigma_spatial).ceil() as usize;
+    let side = 2 * radius + 1;
+    let spatial: Vec<f64> = (0..side * side)
+        .map(|i| {
+            let (dx, dy) = (i % side, i / side);
+            let d2 = dx.abs_diff(radius).pow(2) + dy.abs_diff(radius).pow(2);
+            (-(d2 as f64) / (2.0 * sigma_spatial * sigma_spatial)).exp()
+        })
+        .collect();
+    (0..w * h)
+        .map(|i| {
+            let (x, y) = (i % w, i / w);
+            let center = samples[i] as f64;
+            let (mut sum, mut norm) = (0.0, 0.0);
+            for yy in y.saturating_sub(radius)..(y + radius + 1).min(h) {
+                for xx in x.saturating_sub(radius)..(x + radius + 1).min(w) {
+                    let v = samples[yy * w + xx] as f64;
+                    let d = v - center;
+                    let weight = spatial[(yy + radius - y) * side + xx + radius - x]
+                        * (-d * d / (2.0 * sigma_range * sigma_range)).exp();
+                    sum += weight * v;
+                    norm += weight;
+                }
+            }
+            (sum / norm) as f32
+        })
+        .collect()
+}
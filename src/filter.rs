@@ -0,0 +1,120 @@
+//! Selection of the files found under input directories.
+
+use std::{fmt, path, str};
+
+/// Extensions of the formats the decoder reads.
+pub const IMAGE_EXTENSIONS: [&str; 20] = [
+    "png", "jpg", "jpeg", "gif", "bmp", "ico", "tif", "tiff", "webp", "pnm", "pbm", "pgm", "ppm",
+    "pam", "tga", "dds", "hdr", "exr", "ff", "qoi",
+];
+
+/// A shell-style pattern. `*` and `?` match within one path component, `**`
+/// matches any number of whole components and `[...]` matches one character
+/// of a set such as `[abc]`, `[a-z]` or `[!0-9]`. Patterns without a `/`
+/// are matched against the file name, others against the path relative to
+/// the input directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Glob(String);
+
+impl Glob {
+    pub fn matches(&self, rel: &path::Path) -> bool {
+        let subject = if self.0.contains('/') {
+            rel.iter()
+                .map(|part| part.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/")
+        } else {
+            rel.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        let (pattern, subject): (Vec<_>, Vec<_>) =
+            (self.0.chars().collect(), subject.chars().collect());
+        matches(&pattern, &subject)
+    }
+}
+
+fn matches(pattern: &[char], s: &[char]) -> bool {
+    match pattern {
+        [] => s.is_empty(),
+        ['*', '*', rest @ ..] => {
+            let rest = rest.strip_prefix(&['/']).unwrap_or(rest);
+            rest.is_empty()
+                || (0..=s.len()).any(|i| (i == 0 || s[i - 1] == '/') && matches(rest, &s[i..]))
+        }
+        ['*', rest @ ..] => (0..=s.len())
+            .take_while(|&i| i == 0 || s[i - 1] != '/')
+            .any(|i| matches(rest, &s[i..])),
+        ['?', rest @ ..] => s.first().is_some_and(|&c| c != '/') && matches(rest, &s[1..]),
+        ['[', class @ ..] => match class.iter().skip(1).position(|&c| c == ']') {
+            Some(end) => {
+                let (set, rest) = (&class[..end + 1], &class[end + 2..]);
+                let (negated, set) = match set {
+                    ['!', set @ ..] if !set.is_empty() => (true, set),
+                    set => (false, set),
+                };
+                s.first().is_some_and(|&c| {
+                    c != '/' && in_set(set, c) != negated && matches(rest, &s[1..])
+                })
+            }
+            None => s.first() == Some(&'[') && matches(class, &s[1..]),
+        },
+        [c, rest @ ..] => s.first() == Some(c) && matches(rest, &s[1..]),
+    }
+}
+
+fn in_set(set: &[char], c: char) -> bool {
+    match set {
+        [] => false,
+        [lo, '-', hi, rest @ ..] => (*lo..=*hi).contains(&c) || in_set(rest, c),
+        [x, rest @ ..] => *x == c || in_set(rest, c),
+    }
+}
+
+impl str::FromStr for Glob {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err("empty pattern".to_owned());
+        }
+        Ok(Glob(s.to_owned()))
+    }
+}
+
+impl fmt::Display for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Which of the files found under input directories are processed. Files
+/// given directly are always processed.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    /// If not empty, only files matching one of these are processed.
+    pub include: Vec<Glob>,
+    pub exclude: Vec<Glob>,
+    /// Extensions of the files to process, compared case-insensitively.
+    pub extensions: Vec<String>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            extensions: IMAGE_EXTENSIONS.map(String::from).to_vec(),
+        }
+    }
+}
+
+impl Filter {
+    /// Whether to process the file at `rel` relative to its input directory.
+    pub fn accepts(&self, rel: &path::Path) -> bool {
+        let extension = rel.extension().map(|ext| ext.to_string_lossy());
+        extension.is_some_and(|ext| self.extensions.iter().any(|e| e.eq_ignore_ascii_case(&ext)))
+            && (self.include.is_empty() || self.include.iter().any(|glob| glob.matches(rel)))
+            && !self.exclude.iter().any(|glob| glob.matches(rel))
+    }
+}
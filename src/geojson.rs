@@ -0,0 +1,58 @@
+use crate::Segmentation;
+use std::io;
+
+/// Writes the accepted blobs as a GeoJSON FeatureCollection of polygons in
+/// pixel coordinates, with the blob ID and measurements as properties.
+pub fn write(out: &mut impl io::Write, segmentation: &Segmentation) -> io::Result<()> {
+    writeln!(out, "{{\"type\":\"FeatureCollection\",\"features\":[")?;
+    for (i, blob) in segmentation.blobs.iter().enumerate() {
+        let contour = blob.contour();
+        let rings: Vec<String> = [&contour.exterior]
+            .into_iter()
+            .chain(&contour.holes)
+            .map(|ring| {
+                let points: Vec<String> = ring
+                    .iter()
+                    .chain(ring.first())
+                    .map(|(x, y)| format!("[{x},{y}]"))
+                    .collect();
+                format!("[{}]", points.join(","))
+            })
+            .collect();
+        let intensity = match blob.intensity {
+            Some(intensity) => (
+                segmentation.depth.format_mean(intensity.mean),
+                intensity.min.to_string(),
+                intensity.max.to_string(),
+            ),
+            None => ("null".to_owned(), "null".to_owned(), "null".to_owned()),
+        };
+        writeln!(
+            out,
+            "{{\"type\":\"Feature\",\"id\":{id},\"geometry\":{{\"type\":\"Polygon\",\"coordinates\":[{}]}},\
+             \"properties\":{{\"objectType\":\"detection\",\"id\":{id},\"centroid_x\":{:.3},\"centroid_y\":{:.3},\
+             \"area\":{},\"bbox_x\":{},\"bbox_y\":{},\"bbox_width\":{},\"bbox_height\":{},\"perimeter\":{:.3},\"circularity\":{:.4},\"solidity\":{:.4},\
+             \"eccentricity\":{:.4},\"aspect_ratio\":{:.4},\
+             \"mean_intensity\":{},\"min_intensity\":{},\"max_intensity\":{}}}}}{}",
+            rings.join(","),
+            blob.centroid.0,
+            blob.centroid.1,
+            blob.area,
+            blob.bbox.x,
+            blob.bbox.y,
+            blob.bbox.width,
+            blob.bbox.height,
+            blob.perimeter,
+            blob.circularity,
+            blob.solidity,
+            blob.eccentricity,
+            blob.aspect_ratio,
+            intensity.0,
+            intensity.1,
+            intensity.2,
+            if i + 1 < segmentation.blobs.len() { "," } else { "" },
+            id = i + 1,
+        )?;
+    }
+    writeln!(out, "]}}")
+}
@@ -0,0 +1,80 @@
+use crate::Segmentation;
+use std::{error, fmt, io, str};
+use tiff::encoder::{colortype, TiffEncoder};
+
+/// Sample width of a label image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LabelDepth {
+    U16,
+    U32,
+}
+
+impl str::FromStr for LabelDepth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "16" => Ok(LabelDepth::U16),
+            "32" => Ok(LabelDepth::U32),
+            _ => Err(format!("unsupported label depth {s:?} (expected 16 or 32)")),
+        }
+    }
+}
+
+impl fmt::Display for LabelDepth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LabelDepth::U16 => "16",
+            LabelDepth::U32 => "32",
+        })
+    }
+}
+
+impl Segmentation {
+    /// Returns a row-major label image where background pixels are 0 and every
+    /// accepted blob is filled with its ID.
+    pub fn labels(&self) -> Vec<u32> {
+        let mut out = vec![0; (self.width * self.height) as usize];
+        for (i, blob) in self.blobs.iter().enumerate() {
+            for &(x, y) in &blob.pixels {
+                out[(y * self.width + x) as usize] = i as u32 + 1;
+            }
+        }
+        out
+    }
+}
+
+/// Writes the label image of a segmentation as a single-page grayscale TIFF.
+pub fn write_tiff(
+    out: impl io::Write + io::Seek,
+    segmentation: &Segmentation,
+    depth: LabelDepth,
+) -> Result<(), Box<dyn Send + Sync + error::Error>> {
+    write_tiff_stack(out, std::slice::from_ref(segmentation), depth)
+}
+
+/// Writes the label images of the frames of a stack as the pages of one
+/// grayscale TIFF. IDs restart at 1 on every page.
+pub fn write_tiff_stack(
+    out: impl io::Write + io::Seek,
+    frames: &[Segmentation],
+    depth: LabelDepth,
+) -> Result<(), Box<dyn Send + Sync + error::Error>> {
+    let mut encoder = TiffEncoder::new(out)?;
+    for segmentation in frames {
+        let (width, height) = (segmentation.width, segmentation.height);
+        let labels = segmentation.labels();
+        match depth {
+            LabelDepth::U16 => {
+                let labels = labels
+                    .into_iter()
+                    .map(u16::try_from)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| "Too many blobs for a 16 bit label image")?;
+                encoder.write_image::<colortype::Gray16>(width, height, &labels)?;
+            }
+            LabelDepth::U32 => encoder.write_image::<colortype::Gray32>(width, height, &labels)?,
+        }
+    }
+    Ok(())
+}
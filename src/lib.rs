@@ -0,0 +1,38 @@
+//! Segmentation of dark or bright, roughly round dots in micrographs, from
+//! grayscale images or a chosen channel or stain of color ones.
+//!
+//! The [`Segmenter`] works purely in memory: it takes a decoded image and
+//! returns a [`Segmentation`] describing every candidate blob it found, which
+//! of them were accepted and why the others were rejected.
+
+pub mod background;
+mod blob;
+pub mod ccl;
+pub mod channel;
+pub mod coco;
+mod contour;
+pub mod csv;
+pub mod denoise;
+pub mod geojson;
+mod json;
+pub mod label;
+pub mod morphology;
+pub mod overlay;
+pub mod plane;
+pub mod report;
+mod segmenter;
+pub mod threshold;
+pub mod track;
+pub mod watershed;
+
+pub use background::Background;
+pub use blob::{Blob, BoundingBox, Connectivity, Intensity};
+pub use channel::Channel;
+pub use contour::Contour;
+pub use denoise::Denoise;
+pub use label::LabelDepth;
+pub use morphology::Element;
+pub use plane::{Depth, Plane, Units};
+pub use segmenter::{Levels, Rejection, Segmentation, SegmentationParams, Segmenter};
+pub use threshold::{LocalMethod, Polarity, Threshold, ThresholdMethod};
+pub use track::{Linking, TrackParams};
@@ -1,16 +1,330 @@
-use clap::Parser;
-use image::{io::Reader as ImageReader, ImageBuffer, Luma};
+use clap::{ArgAction, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use filter::{Filter, Glob};
+use image::{io::Reader as ImageReader, DynamicImage};
+use imgseg::{
+    coco::{self, CocoImage, MaskFormat},
+    csv, geojson, label, overlay,
+    plane::{self, TiffPage},
+    report::{self, ReportImage},
+    track, Background, Channel, Connectivity, Denoise, Depth, Element, LabelDepth, Levels, Linking,
+    Plane, Polarity, Segmentation, SegmentationParams, Segmenter, Threshold, TrackParams, Units,
+};
 use rayon::prelude::*;
 use rfd::{FileDialog, MessageButtons, MessageDialog};
-use std::{collections, env, error, f64, fs, path, time};
+use std::{
+    collections::BTreeMap,
+    env, error, fs,
+    io::{self, Write},
+    path, time,
+};
+use tiff::encoder::{colortype, TiffEncoder};
+
+mod config;
+mod filter;
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_override_self = true)]
 struct Args {
+    #[arg(
+        long,
+        help = "TOML file of settings to use unless overridden by the flags given here"
+    )]
+    config: Option<path::PathBuf>,
+    #[arg(
+        long,
+        requires = "config",
+        help = "Profile of the config file to apply on top of its top-level settings"
+    )]
+    profile: Option<String>,
+    #[arg(long, help = "Print the resolved settings as a config file and exit")]
+    print_config: bool,
     #[arg(short, long, num_args = 0.., help = "List of files or directories to process")]
     images: Vec<path::PathBuf>,
     #[arg(short, long, help = "Output directory", default_value = "out")]
     out_dir: path::PathBuf,
+    #[arg(long, help = "Channel of color images to segment: r, g, b, a, luma, hue, saturation, value, weights:<r>:<g>:<b>, hematoxylin, eosin or dab", default_value_t = SegmentationParams::default().channel)]
+    channel: Channel,
+    #[arg(long, help = "Object polarity: dark, bright or auto", default_value_t = SegmentationParams::default().polarity)]
+    polarity: Polarity,
+    #[arg(long, help = "Smoothing before thresholding: none, gaussian:<sigma>, median:<radius> or bilateral:<spatial sigma>:<range sigma>", default_value_t = SegmentationParams::default().denoise)]
+    denoise: Denoise,
+    #[arg(long, help = "Background subtraction before thresholding: none, rolling-ball:<radius> or top-hat:<radius>", default_value_t = SegmentationParams::default().background)]
+    background: Background,
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        default_value_t = false,
+        help = "Also write the subtracted background as a PNG"
+    )]
+    save_background: bool,
+    #[arg(long, help = "Threshold selection: fixed, auto:otsu|triangle|li|mean or local:sauvola|niblack|mean", default_value_t = SegmentationParams::default().threshold)]
+    threshold: Threshold,
+    #[arg(long, help = "Units of the levels: native sample values or normalized fractions of full scale", default_value_t = SegmentationParams::default().units)]
+    units: Units,
+    #[arg(long, help = "Brightest level that starts a new blob", default_value_t = SegmentationParams::default().max_black)]
+    max_black: f64,
+    #[arg(long, help = "Brightest level that extends an existing blob", default_value_t = SegmentationParams::default().max_gray)]
+    max_gray: f64,
+    #[arg(long, help = "Seed level relative to an automatic threshold", default_value_t = SegmentationParams::default().seed_offset, allow_hyphen_values = true)]
+    seed_offset: f64,
+    #[arg(long, help = "Grow level relative to an automatic threshold", default_value_t = SegmentationParams::default().grow_offset, allow_hyphen_values = true)]
+    grow_offset: f64,
+    #[arg(long, help = "Pixel connectivity of blobs: 4 or 8", default_value_t = SegmentationParams::default().connectivity)]
+    connectivity: Connectivity,
+    #[arg(long, help = "Window size in pixels for local thresholds", default_value_t = SegmentationParams::default().window)]
+    window: u32,
+    #[arg(long, help = "Standard deviation weight for Sauvola and Niblack thresholds", default_value_t = SegmentationParams::default().local_k, allow_hyphen_values = true)]
+    local_k: f64,
+    #[arg(long, help = "Constant subtracted from the local mean by local:mean", default_value_t = SegmentationParams::default().local_c, allow_hyphen_values = true)]
+    local_c: f64,
+    #[arg(long, help = "Radius of the binary opening of the thresholded mask, 0 to disable", default_value_t = SegmentationParams::default().open_radius)]
+    open_radius: u32,
+    #[arg(long, help = "Radius of the binary closing of the thresholded mask, 0 to disable", default_value_t = SegmentationParams::default().close_radius)]
+    close_radius: u32,
+    #[arg(long, help = "Structuring element for opening and closing: disk, square or diamond", default_value_t = SegmentationParams::default().element)]
+    element: Element,
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        default_value_t = SegmentationParams::default().fill_holes,
+        help = "Fill holes in the thresholded mask"
+    )]
+    fill_holes: bool,
+    #[arg(long, help = "Drop components below this many pixels before measuring them", default_value_t = SegmentationParams::default().discard_below)]
+    discard_below: usize,
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        default_value_t = SegmentationParams::default().split,
+        help = "Split touching blobs along the watershed of their distance transform"
+    )]
+    split: bool,
+    #[arg(long, help = "Smallest distance in pixels between the centers of split blobs", default_value_t = SegmentationParams::default().split_min_distance)]
+    split_min_distance: f64,
+    #[arg(long, help = "Smallest accepted blob size in pixels", default_value_t = SegmentationParams::default().min_area)]
+    min_area: usize,
+    #[arg(long, help = "Largest accepted blob size in pixels", default_value_t = SegmentationParams::default().max_area)]
+    max_area: usize,
+    #[arg(long, help = "Allowed distance from the centroid relative to the radius of a disc of equal area", default_value_t = SegmentationParams::default().radius_tolerance)]
+    radius_tolerance: f64,
+    #[arg(long, help = "Smallest accepted circularity 4*pi*area/perimeter^2", default_value_t = SegmentationParams::default().min_circularity)]
+    min_circularity: f64,
+    #[arg(long, help = "Largest accepted circularity 4*pi*area/perimeter^2", default_value_t = SegmentationParams::default().max_circularity)]
+    max_circularity: f64,
+    #[arg(long, help = "Smallest accepted solidity area/convex hull area", default_value_t = SegmentationParams::default().min_solidity)]
+    min_solidity: f64,
+    #[arg(long, help = "Largest accepted solidity area/convex hull area", default_value_t = SegmentationParams::default().max_solidity)]
+    max_solidity: f64,
+    #[arg(long, help = "Smallest accepted eccentricity of the equivalent ellipse", default_value_t = SegmentationParams::default().min_eccentricity)]
+    min_eccentricity: f64,
+    #[arg(long, help = "Largest accepted eccentricity of the equivalent ellipse", default_value_t = SegmentationParams::default().max_eccentricity)]
+    max_eccentricity: f64,
+    #[arg(long, help = "Smallest accepted major/minor axis ratio of the equivalent ellipse", default_value_t = SegmentationParams::default().min_aspect_ratio)]
+    min_aspect_ratio: f64,
+    #[arg(long, help = "Largest accepted major/minor axis ratio of the equivalent ellipse", default_value_t = SegmentationParams::default().max_aspect_ratio)]
+    max_aspect_ratio: f64,
+    #[arg(
+        long,
+        value_enum,
+        help = "Write per-blob measurements to a CSV next to each mask or to one combined measurements.csv"
+    )]
+    csv: Option<CsvMode>,
+    #[arg(
+        long,
+        help = "Also write a 16 or 32 bit TIFF label image with one ID per blob"
+    )]
+    labels: Option<LabelDepth>,
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        default_value_t = false,
+        help = "Also write a PNG outlining accepted and rejected blobs over the input"
+    )]
+    overlay: bool,
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        default_value_t = false,
+        help = "Label each accepted blob with its ID in the overlay"
+    )]
+    overlay_ids: bool,
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        default_value_t = false,
+        help = "Also write the outline of each accepted blob as a GeoJSON polygon"
+    )]
+    geojson: bool,
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        default_value_t = false,
+        help = "Write COCO instance annotations for the whole batch to coco.json"
+    )]
+    coco: bool,
+    #[arg(
+        long,
+        help = "Category name of the COCO annotations",
+        default_value = "dot"
+    )]
+    coco_category: String,
+    #[arg(long, help = "COCO mask encoding: polygon or rle", default_value_t = MaskFormat::default())]
+    coco_masks: MaskFormat,
+    #[arg(
+        long,
+        value_enum,
+        help = "Write the mask and labels of multi-page TIFFs as matching stacks or as numbered per-frame files",
+        default_value_t = StackOutput::default()
+    )]
+    stack_output: StackOutput,
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        default_value_t = false,
+        help = "Link the blobs of the frames of each stack, or of the images of each directory in order of their names, into tracks written to a tracks CSV"
+    )]
+    track: bool,
+    #[arg(long, help = "Linking of blobs between frames: nearest or hungarian", default_value_t = TrackParams::default().linking)]
+    linking: Linking,
+    #[arg(long, help = "Farthest a blob may move between frames and stay on its track, in pixels", default_value_t = TrackParams::default().max_displacement)]
+    max_displacement: f64,
+    #[arg(long, help = "Number of frames a track may go undetected and still be continued", default_value_t = TrackParams::default().max_gap)]
+    max_gap: usize,
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        default_value_t = false,
+        help = "Write report.html with blob counts, thumbnails and size histograms of the whole batch"
+    )]
+    report: bool,
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        default_value_t = false,
+        help = "Write summary.csv with one row of statistics per image"
+    )]
+    summary: bool,
+    #[arg(
+        long,
+        help = "Only process files under input directories that match one of these globs, by name or, for patterns with a /, by relative path"
+    )]
+    include: Vec<Glob>,
+    #[arg(
+        long,
+        help = "Skip files under input directories that match one of these globs"
+    )]
+    exclude: Vec<Glob>,
+    #[arg(long, value_delimiter = ',', help = "Extensions of the files under input directories to process", default_values_t = Filter::default().extensions)]
+    extensions: Vec<String>,
+}
+
+impl Args {
+    /// Parses the command line on top of the settings of the config file it
+    /// names. Returns `None` once the settings are printed for
+    /// `--print-config`.
+    fn load() -> Result<Option<Args>, Box<dyn Send + Sync + error::Error>> {
+        let command = Args::command();
+        let mut matches = command.clone().get_matches();
+        let mut args = Args::from_arg_matches(&matches)?;
+        if let Some(path) = &args.config {
+            let settings = config::arguments(&command, &matches, path, args.profile.as_deref())?;
+            let argv = env::args_os().chain(settings);
+            matches = command.clone().get_matches_from(argv);
+            args = Args::from_arg_matches(&matches)?;
+        }
+        if args.print_config {
+            print!("{}", config::print(&command, &matches));
+            return Ok(None);
+        }
+        Ok(Some(args))
+    }
+
+    fn params(&self) -> SegmentationParams {
+        SegmentationParams {
+            channel: self.channel,
+            polarity: self.polarity,
+            denoise: self.denoise,
+            background: self.background,
+            threshold: self.threshold,
+            units: self.units,
+            max_black: self.max_black,
+            max_gray: self.max_gray,
+            seed_offset: self.seed_offset,
+            grow_offset: self.grow_offset,
+            connectivity: self.connectivity,
+            window: self.window,
+            local_k: self.local_k,
+            local_c: self.local_c,
+            open_radius: self.open_radius,
+            close_radius: self.close_radius,
+            element: self.element,
+            fill_holes: self.fill_holes,
+            discard_below: self.discard_below,
+            split: self.split,
+            split_min_distance: self.split_min_distance,
+            min_area: self.min_area,
+            max_area: self.max_area,
+            radius_tolerance: self.radius_tolerance,
+            min_circularity: self.min_circularity,
+            max_circularity: self.max_circularity,
+            min_solidity: self.min_solidity,
+            max_solidity: self.max_solidity,
+            min_eccentricity: self.min_eccentricity,
+            max_eccentricity: self.max_eccentricity,
+            min_aspect_ratio: self.min_aspect_ratio,
+            max_aspect_ratio: self.max_aspect_ratio,
+        }
+    }
+
+    fn filter(&self) -> Filter {
+        Filter {
+            include: self.include.clone(),
+            exclude: self.exclude.clone(),
+            extensions: self.extensions.clone(),
+        }
+    }
+
+    fn outputs(&self) -> Outputs {
+        Outputs {
+            csv: self.csv,
+            labels: self.labels,
+            overlay: self.overlay || self.overlay_ids,
+            overlay_ids: self.overlay_ids,
+            background: self.save_background,
+            geojson: self.geojson,
+            coco: self
+                .coco
+                .then(|| (self.coco_category.clone(), self.coco_masks)),
+            stack: self.stack_output,
+            track: self.track.then_some(TrackParams {
+                linking: self.linking,
+                max_displacement: self.max_displacement,
+                max_gap: self.max_gap,
+            }),
+            report: self.report,
+            summary: self.summary,
+        }
+    }
 }
 
 enum GenResult {
@@ -76,14 +390,237 @@ impl Iterator for GenResult {
     }
 }
 
-const MAX_BLACK: u8 = 40;
-const MAX_GRAY: u8 = 60;
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum CsvMode {
+    PerImage,
+    Combined,
+}
+
+/// How the masks and label images of multi-page inputs are written.
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum StackOutput {
+    /// One multi-page TIFF each, matching the input.
+    #[default]
+    Stack,
+    /// One numbered file per frame.
+    Frames,
+}
+
+#[derive(Default)]
+struct Outputs {
+    csv: Option<CsvMode>,
+    labels: Option<LabelDepth>,
+    overlay: bool,
+    overlay_ids: bool,
+    background: bool,
+    geojson: bool,
+    coco: Option<(String, MaskFormat)>,
+    stack: StackOutput,
+    track: Option<TrackParams>,
+    report: bool,
+    summary: bool,
+}
 
+struct Processed {
+    source: path::PathBuf,
+    target: path::PathBuf,
+    levels: String,
+    rows: Vec<u8>,
+    summary: Vec<u8>,
+    coco: Vec<CocoImage>,
+    /// Accepted blob centroids of every frame.
+    centroids: Vec<Vec<(f64, f64)>>,
+    report: Option<ReportImage>,
+}
+
+/// Decodes the frames of an image and extracts the channel to segment.
+/// Every page of a TIFF with a supported sample type is read directly, so
+/// that floating point samples survive. Other formats, and single TIFF pages
+/// of unsupported types, go through the generic decoder and yield one frame.
+fn load(
+    path: &path::Path,
+    channel: Channel,
+) -> Result<Vec<(DynamicImage, Plane)>, Box<dyn Send + Sync + error::Error>> {
+    let is_tiff = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("tif") || ext.eq_ignore_ascii_case("tiff"));
+    if is_tiff {
+        if let Some(pages) = plane::read_tiff_stack(io::BufReader::new(fs::File::open(path)?))? {
+            return Ok(pages
+                .into_iter()
+                .map(|page| match page {
+                    TiffPage::Gray(plane) => (plane.to_dynamic(), channel.extract_gray(plane)),
+                    TiffPage::Color(img) => {
+                        let plane = channel.extract(&img);
+                        (img, plane)
+                    }
+                })
+                .collect());
+        }
+    }
+    let img = ImageReader::open(path)?.decode()?;
+    let plane = channel.extract(&img);
+    Ok(vec![(img, plane)])
+}
+
+/// The path of a per-frame output: `name.suffix` for single images and
+/// `name.0003.suffix` for the fourth frame of a stack.
+fn frame_path(target: &path::Path, frame: Option<usize>, suffix: &str) -> path::PathBuf {
+    match frame {
+        Some(i) => target.with_extension(format!("{i:04}.{suffix}")),
+        None => target.with_extension(suffix),
+    }
+}
+
+/// Segments every frame of one file and writes its outputs.
+fn process(
+    segmenter: &Segmenter,
+    channel: Channel,
+    out_dir: &path::Path,
+    outputs: &Outputs,
+    realpath: path::PathBuf,
+    rel: &path::Path,
+) -> Result<Processed, Box<dyn Send + Sync + error::Error>> {
+    let frames = load(&realpath, channel)?;
+    let stack = frames.len() > 1;
+    let target = out_dir.join(rel);
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file_name = rel
+        .iter()
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    let mut segmentations = Vec::with_capacity(frames.len());
+    let mut rows = Vec::new();
+    let mut summary = Vec::new();
+    let mut coco = Vec::new();
+    for (i, (img, plane)) in frames.iter().enumerate() {
+        let started = time::Instant::now();
+        let segmentation = segmenter.segment_plane(plane);
+        let elapsed = started.elapsed();
+        let frame = stack.then_some(i);
+        if !stack || outputs.stack == StackOutput::Frames {
+            let mask_ext = target.extension().unwrap_or_default().to_string_lossy();
+            segmentation
+                .mask()
+                .save(frame_path(&target, frame, &mask_ext))?;
+            if let Some(depth) = outputs.labels {
+                let file = fs::File::create(frame_path(&target, frame, "labels.tif"))?;
+                label::write_tiff(io::BufWriter::new(file), &segmentation, depth)?;
+            }
+        }
+        if outputs.overlay {
+            overlay::overlay(img, &segmentation, outputs.overlay_ids).save(frame_path(
+                &target,
+                frame,
+                "overlay.png",
+            ))?;
+        }
+        if let Some(background) = segmentation
+            .background
+            .as_ref()
+            .filter(|_| outputs.background)
+        {
+            if background.depth == Depth::F32 {
+                let file = fs::File::create(frame_path(&target, frame, "background.tif"))?;
+                background.write_tiff(io::BufWriter::new(file))?;
+            } else {
+                background
+                    .to_dynamic()
+                    .save(frame_path(&target, frame, "background.png"))?;
+            }
+        }
+        if outputs.geojson {
+            let file = fs::File::create(frame_path(&target, frame, "geojson"))?;
+            let mut file = io::BufWriter::new(file);
+            geojson::write(&mut file, &segmentation)?;
+            file.flush()?;
+        }
+        if outputs.csv.is_some() {
+            csv::write_rows(&mut rows, &realpath, i, &segmentation)?;
+        }
+        if outputs.summary {
+            csv::write_summary(&mut summary, &realpath, i, &segmentation, elapsed)?;
+        }
+        if let Some((_, format)) = &outputs.coco {
+            let mut image = CocoImage::new(file_name.clone(), &segmentation, *format);
+            image.frame = frame;
+            coco.push(image);
+        }
+        segmentations.push(segmentation);
+    }
+    if stack && outputs.stack == StackOutput::Stack {
+        let file = fs::File::create(&target)?;
+        write_mask_stack(io::BufWriter::new(file), &segmentations)?;
+        if let Some(depth) = outputs.labels {
+            let file = fs::File::create(target.with_extension("labels.tif"))?;
+            label::write_tiff_stack(io::BufWriter::new(file), &segmentations, depth)?;
+        }
+    }
+    if outputs.csv == Some(CsvMode::PerImage) {
+        let mut file = fs::File::create(target.with_extension("csv"))?;
+        writeln!(file, "{}", csv::HEADER)?;
+        file.write_all(&rows)?;
+    }
+    let centroids = segmentations
+        .iter()
+        .map(|segmentation| {
+            segmentation
+                .blobs
+                .iter()
+                .map(|blob| blob.centroid)
+                .collect()
+        })
+        .collect();
+    let report = outputs
+        .report
+        .then(|| ReportImage::new(file_name, &frames[0].0, &segmentations))
+        .transpose()?;
+    let levels = match &segmentations[..] {
+        [segmentation] => describe_levels(segmentation.polarity, &segmentation.levels),
+        frames => format!("{} frames", frames.len()),
+    };
+    Ok(Processed {
+        source: realpath,
+        target,
+        levels,
+        rows,
+        summary,
+        coco,
+        centroids,
+        report,
+    })
+}
+
+/// Writes the masks of the frames of a stack as the pages of one TIFF.
+fn write_mask_stack(
+    out: impl io::Write + io::Seek,
+    frames: &[Segmentation],
+) -> Result<(), Box<dyn Send + Sync + error::Error>> {
+    let mut encoder = TiffEncoder::new(out)?;
+    for segmentation in frames {
+        let mask = segmentation.mask();
+        encoder.write_image::<colortype::Gray8>(mask.width(), mask.height(), mask.as_raw())?;
+    }
+    Ok(())
+}
+
+/// Segments the given files and the files under the given directories that
+/// pass `filter`. Returns the target of every segmented file, `None` for
+/// every skipped one, and the errors.
 fn segment(
     images: impl IntoParallelIterator<Item = path::PathBuf>,
     out_dir: &path::Path,
-) -> Vec<Result<path::PathBuf, Box<dyn Send + Sync + error::Error>>> {
-    images
+    params: SegmentationParams,
+    outputs: &Outputs,
+    filter: &Filter,
+) -> Vec<Result<Option<path::PathBuf>, Box<dyn Send + Sync + error::Error>>> {
+    let channel = params.channel;
+    let segmenter = Segmenter::new(params);
+    let mut results: Vec<_> = images
         .into_par_iter()
         .flat_map(|base| {
             let res: GenResult = base.clone().into();
@@ -99,93 +636,211 @@ fn segment(
                             )?
                             .into()
                     };
-                    Ok((rp, rel))
+                    Ok((rp, rel, is_dir))
                 })
             })
             .par_bridge()
         })
         .map(|prev| {
-            let (realpath, rel) = prev?;
-            let img = ImageReader::open(&realpath)?.decode()?;
-            let img = img.grayscale();
-            let img = img.as_luma8().ok_or("Expected 8 bit grayscale image")?;
-            let mut out = ImageBuffer::<Luma<u8>, Vec<u8>>::new(img.width(), img.height());
-            let mut vis = vec![false; (img.width() * img.height()) as usize];
-            let mut blobs = Vec::new();
-            for (x, y, &px) in img.enumerate_pixels() {
-                if vis[(y * img.width() + x) as usize] || px.0[0] > MAX_BLACK {
-                    continue;
-                }
-                let mut q = collections::LinkedList::new();
-                let mut blob = vec![(x, y)];
-                q.push_back((x, y));
-                while let Some((x, y)) = q.pop_front() {
-                    if vis[(y * img.width() + x) as usize] {
-                        continue;
-                    }
-                    vis[(y * img.width() + x) as usize] = true;
-                    blob.push((x, y));
-                    for (dx, dy) in [(0, 1), (0, -1), (1, 0), (-1, 0)] {
-                        let (cx, cy) = (x.wrapping_add_signed(dx), y.wrapping_add_signed(dy));
-                        if cx >= img.width()
-                            || cy >= img.height()
-                            || vis[(cy * img.width() + cx) as usize]
-                            || img[(cx, cy)].0[0] > MAX_GRAY
-                        {
-                            continue;
-                        }
-                        q.push_back((cx, cy));
-                    }
-                }
-                if blob.len() > 1 {
-                    blobs.push(blob);
-                }
-            }
-            for blob in blobs {
-                if blob.len() < 3 || blob.len() > 10000 {
-                    continue;
-                }
-                let (sx, sy) = blob
-                    .iter()
-                    .copied()
-                    .map(|(x, y)| (x as f64, y as f64))
-                    .fold((0.0, 0.0), |(ax, ay), (bx, by)| (ax + bx, ay + by));
-                let (cx, cy) = (sx / blob.len() as f64, sy / blob.len() as f64);
-                let expected_radius = blob.len() as f64 / f64::consts::PI;
-                let allowed_radius = expected_radius * 1.5;
-                if blob
-                    .iter()
-                    .copied()
-                    .any(|(x, y)| (x as f64 - cx).hypot(y as f64 - cy) > allowed_radius)
-                {
-                    continue;
-                }
-                for pt in blob {
-                    out[pt] = [255].into();
-                }
-            }
-            image::imageops::colorops::invert(&mut out);
-            let target = out_dir.join(rel);
-            if let Some(parent) = target.parent() {
-                fs::create_dir_all(parent)?;
+            let (realpath, rel, is_dir) = prev?;
+            if is_dir && !filter.accepts(&rel) {
+                println!("Skipped {}", realpath.display());
+                return Ok(None);
             }
-            out.save(&target)?;
-            Ok((realpath, target))
+            let source = realpath.display().to_string();
+            process(&segmenter, channel, out_dir, outputs, realpath, &rel)
+                .map(Some)
+                .map_err(|err| format!("{source}: {err}").into())
         })
         .inspect(|res| {
-            if let Ok((realpath, target)) = res {
-                println!("Segmented {} -> {}", realpath.display(), target.display());
+            if let Ok(Some(processed)) = res {
+                println!(
+                    "Segmented {} -> {} ({})",
+                    processed.source.display(),
+                    processed.target.display(),
+                    processed.levels
+                );
             }
         })
-        .map(|res| res.map(|(_, target)| target))
+        .collect();
+    // Segmented files in order of their paths, then skipped ones, then errors.
+    let rank = |res: &Result<Option<Processed>, _>| match res {
+        Ok(Some(_)) => 0,
+        Ok(None) => 1,
+        Err(_) => 2,
+    };
+    results.sort_by(|a, b| match (a, b) {
+        (Ok(Some(a)), Ok(Some(b))) => a.source.cmp(&b.source),
+        (a, b) => rank(a).cmp(&rank(b)),
+    });
+    let processed: Vec<_> = results.iter().flatten().flatten().collect();
+    let mut errors = Vec::new();
+    if outputs.csv == Some(CsvMode::Combined) {
+        errors.extend(write_combined_csv(out_dir, &processed).err());
+    }
+    if outputs.summary {
+        errors.extend(write_summary(out_dir, &processed).err());
+    }
+    if let Some((category, _)) = &outputs.coco {
+        errors.extend(write_coco(out_dir, category, &processed).err());
+    }
+    if let Some(params) = &outputs.track {
+        errors.extend(write_tracks(&processed, params).err());
+    }
+    if outputs.report {
+        let messages: Vec<_> = results
+            .iter()
+            .filter_map(|res| res.as_ref().err())
+            .chain(&errors)
+            .map(|err| err.to_string())
+            .collect();
+        errors.extend(write_report(out_dir, &processed, &messages).err());
+    }
+    results.extend(errors.into_iter().map(Err));
+    results
+        .into_iter()
+        .map(|res| res.map(|processed| processed.map(|processed| processed.target)))
         .collect()
 }
 
+fn write_combined_csv(
+    out_dir: &path::Path,
+    processed: &[&Processed],
+) -> Result<(), Box<dyn Send + Sync + error::Error>> {
+    fs::create_dir_all(out_dir)?;
+    let mut file = io::BufWriter::new(fs::File::create(out_dir.join("measurements.csv"))?);
+    writeln!(file, "{}", csv::HEADER)?;
+    for processed in processed {
+        file.write_all(&processed.rows)?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+fn write_summary(
+    out_dir: &path::Path,
+    processed: &[&Processed],
+) -> Result<(), Box<dyn Send + Sync + error::Error>> {
+    fs::create_dir_all(out_dir)?;
+    let mut file = io::BufWriter::new(fs::File::create(out_dir.join("summary.csv"))?);
+    writeln!(file, "{}", csv::SUMMARY_HEADER)?;
+    for processed in processed {
+        file.write_all(&processed.summary)?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+fn write_coco(
+    out_dir: &path::Path,
+    category: &str,
+    processed: &[&Processed],
+) -> Result<(), Box<dyn Send + Sync + error::Error>> {
+    let images: Vec<_> = processed.iter().flat_map(|p| &p.coco).collect();
+    let mut ids: Vec<_> = images.iter().map(|image| image.id()).collect();
+    ids.sort_unstable();
+    if ids.windows(2).any(|w| w[0] == w[1]) {
+        return Err("Duplicate COCO image IDs".into());
+    }
+    fs::create_dir_all(out_dir)?;
+    let mut file = io::BufWriter::new(fs::File::create(out_dir.join("coco.json"))?);
+    coco::write(&mut file, category, &images)?;
+    file.flush()?;
+    Ok(())
+}
+
+fn write_report(
+    out_dir: &path::Path,
+    processed: &[&Processed],
+    errors: &[String],
+) -> Result<(), Box<dyn Send + Sync + error::Error>> {
+    let images: Vec<_> = processed.iter().filter_map(|p| p.report.as_ref()).collect();
+    fs::create_dir_all(out_dir)?;
+    let mut file = io::BufWriter::new(fs::File::create(out_dir.join("report.html"))?);
+    report::write(&mut file, &images, errors)?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Links the blobs of every sequence into tracks. Stacks are sequences of
+/// their own and write `name.tracks.csv`; single images form one sequence
+/// per directory, in order of their names, and write `tracks.csv` there.
+fn write_tracks(
+    processed: &[&Processed],
+    params: &TrackParams,
+) -> Result<(), Box<dyn Send + Sync + error::Error>> {
+    let mut sequences: BTreeMap<path::PathBuf, Vec<&Processed>> = BTreeMap::new();
+    for &processed in processed {
+        let out = if processed.centroids.len() > 1 {
+            processed.target.with_extension("tracks.csv")
+        } else {
+            processed.target.with_file_name("tracks.csv")
+        };
+        sequences.entry(out).or_default().push(processed);
+    }
+    for (out, sequence) in sequences {
+        let frames: Vec<_> = sequence
+            .iter()
+            .flat_map(|p| p.centroids.iter().map(|c| (p.source.as_path(), &c[..])))
+            .collect();
+        let centroids: Vec<_> = frames.iter().map(|(_, c)| c.to_vec()).collect();
+        let ids = track::link(&centroids, params);
+        let mut file = io::BufWriter::new(fs::File::create(out)?);
+        writeln!(file, "{}", csv::TRACKS_HEADER)?;
+        csv::write_tracks(&mut file, &frames, &ids)?;
+        file.flush()?;
+    }
+    Ok(())
+}
+
+fn describe_levels(polarity: Polarity, levels: &Levels) -> String {
+    let cmp = if polarity == Polarity::Bright {
+        ">="
+    } else {
+        "<="
+    };
+    match *levels {
+        Levels::Global {
+            threshold: Some(t),
+            seed,
+            grow,
+        } => format!(
+            "{polarity}, threshold {}, seed {cmp} {}, grow {cmp} {}",
+            plane::format_level(t),
+            plane::format_level(seed),
+            plane::format_level(grow)
+        ),
+        Levels::Global {
+            threshold: None,
+            seed,
+            grow,
+        } => format!(
+            "{polarity}, seed {cmp} {}, grow {cmp} {}",
+            plane::format_level(seed),
+            plane::format_level(grow)
+        ),
+        Levels::Local { mean } => format!(
+            "{polarity}, local threshold, mean {}",
+            plane::format_level(mean)
+        ),
+    }
+}
+
 fn main() -> Result<(), Box<dyn Send + Sync + error::Error>> {
     let cli = env::args().count() > 1;
     let (start_time, completion) = if cli {
-        let args = Args::parse();
-        (time::Instant::now(), segment(args.images, &args.out_dir))
+        let Some(args) = Args::load()? else {
+            return Ok(());
+        };
+        let (params, outputs, filter) = (args.params(), args.outputs(), args.filter());
+        params.validate()?;
+        if let Some(track) = &outputs.track {
+            track.validate()?;
+        }
+        (
+            time::Instant::now(),
+            segment(args.images, &args.out_dir, params, &outputs, &filter),
+        )
     } else {
         println!("Select folders to process");
         let paths = FileDialog::new()
@@ -200,15 +855,28 @@ fn main() -> Result<(), Box<dyn Send + Sync + error::Error>> {
             .set_file_name("out")
             .save_file()
             .ok_or::<Box<dyn Send + Sync + error::Error>>("No folders selected".into())?;
-        (time::Instant::now(), segment(paths, &out_path))
+        (
+            time::Instant::now(),
+            segment(
+                paths,
+                &out_path,
+                SegmentationParams::default(),
+                &Outputs::default(),
+                &Filter::default(),
+            ),
+        )
     };
     let end_time = time::Instant::now();
     let delta_t = end_time - start_time;
     let message = format!(
-        "Processed {} images in {:.3}s ({} errors)",
-        completion.iter().filter(|v| v.is_ok()).count(),
+        "Processed {} images in {:.3}s ({} errors, {} skipped)",
+        completion
+            .iter()
+            .filter(|v| matches!(v, Ok(Some(_))))
+            .count(),
         delta_t.as_secs_f64(),
-        completion.iter().filter(|v| v.is_err()).count()
+        completion.iter().filter(|v| v.is_err()).count(),
+        completion.iter().filter(|v| matches!(v, Ok(None))).count()
     );
     if cli {
         println!("{}", message);
@@ -0,0 +1,141 @@
+use crate::Connectivity;
+use std::{fmt, str};
+
+/// Shape of the neighbourhood used by binary opening and closing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Element {
+    /// All pixels within the radius by Euclidean distance.
+    #[default]
+    Disk,
+    /// All pixels within the radius along both axes.
+    Square,
+    /// All pixels within the radius by city block distance.
+    Diamond,
+}
+
+impl Element {
+    /// How far the element reaches horizontally on the row `dy` away from
+    /// its center.
+    pub(crate) fn half_width(self, radius: u32, dy: u32) -> u32 {
+        match self {
+            Element::Disk => {
+                let (r, dy) = (radius as u64, dy as u64);
+                ((r * r - dy * dy) as f64).sqrt() as u32
+            }
+            Element::Square => radius,
+            Element::Diamond => radius - dy,
+        }
+    }
+}
+
+impl str::FromStr for Element {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "disk" => Ok(Element::Disk),
+            "square" => Ok(Element::Square),
+            "diamond" => Ok(Element::Diamond),
+            _ => Err(format!(
+                "unknown structuring element {s:?} (expected disk, square or diamond)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Element {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Element::Disk => "disk",
+            Element::Square => "square",
+            Element::Diamond => "diamond",
+        })
+    }
+}
+
+/// Dilates (`erode == false`) or erodes a row-major mask. Pixels beyond the
+/// image border never change the result, so objects touching the border are
+/// not eaten away by erosion.
+fn sweep(mask: &[bool], width: u32, element: Element, radius: u32, erode: bool) -> Vec<bool> {
+    let width = width as usize;
+    let height = mask.len() / width.max(1);
+    let mut out = vec![erode; mask.len()];
+    // Prefix counts of the pixels that decide the outcome on one row: set
+    // pixels for dilation, unset ones for erosion.
+    let mut hits = vec![0u32; width + 1];
+    for y in 0..height {
+        for dy in -(radius as i64)..=radius as i64 {
+            let src = y as i64 + dy;
+            if src < 0 || src >= height as i64 {
+                continue;
+            }
+            let row = &mask[src as usize * width..(src as usize + 1) * width];
+            for (x, &v) in row.iter().enumerate() {
+                hits[x + 1] = hits[x] + (v != erode) as u32;
+            }
+            let reach = element.half_width(radius, dy.unsigned_abs() as u32) as usize;
+            let dst = &mut out[y * width..(y + 1) * width];
+            for (x, px) in dst.iter_mut().enumerate() {
+                let (lo, hi) = (x.saturating_sub(reach), (x + reach + 1).min(width));
+                if hits[hi] > hits[lo] {
+                    *px = !erode;
+                }
+            }
+        }
+    }
+    out
+}
+
+pub fn dilate(mask: &[bool], width: u32, element: Element, radius: u32) -> Vec<bool> {
+    sweep(mask, width, element, radius, false)
+}
+
+pub fn erode(mask: &[bool], width: u32, element: Element, radius: u32) -> Vec<bool> {
+    sweep(mask, width, element, radius, true)
+}
+
+/// Erosion followed by dilation: removes specks and thin protrusions smaller
+/// than the element.
+pub fn open(mask: &[bool], width: u32, element: Element, radius: u32) -> Vec<bool> {
+    dilate(&erode(mask, width, element, radius), width, element, radius)
+}
+
+/// Dilation followed by erosion: bridges gaps and fills dents smaller than
+/// the element.
+pub fn close(mask: &[bool], width: u32, element: Element, radius: u32) -> Vec<bool> {
+    erode(
+        &dilate(mask, width, element, radius),
+        width,
+        element,
+        radius,
+    )
+}
+
+/// Sets every unset pixel that cannot reach the image border through unset
+/// pixels. The background is traversed with the dual of the objects'
+/// connectivity, so that a hole is exactly what their contour calls one.
+pub fn fill_holes(mask: &[bool], width: u32, connectivity: Connectivity) -> Vec<bool> {
+    if mask.is_empty() {
+        return Vec::new();
+    }
+    let height = mask.len() as u32 / width;
+    let mut outside = vec![false; mask.len()];
+    let mut stack: Vec<(u32, u32)> = (0..width)
+        .flat_map(|x| [(x, 0), (x, height - 1)])
+        .chain((0..height).flat_map(|y| [(0, y), (width - 1, y)]))
+        .collect();
+    while let Some((x, y)) = stack.pop() {
+        let i = (y * width + x) as usize;
+        if mask[i] || outside[i] {
+            continue;
+        }
+        outside[i] = true;
+        for &(dx, dy) in connectivity.dual().offsets() {
+            let (nx, ny) = (x.wrapping_add_signed(dx), y.wrapping_add_signed(dy));
+            if nx < width && ny < height {
+                stack.push((nx, ny));
+            }
+        }
+    }
+    outside.into_iter().map(|v| !v).collect()
+}
@@ -0,0 +1,57 @@
+use crate::Segmentation;
+use image::{DynamicImage, Rgb, RgbImage};
+
+/// Outline color of accepted blobs.
+pub const ACCEPTED: Rgb<u8> = Rgb([0, 255, 0]);
+/// Outline color of rejected candidates.
+pub const REJECTED: Rgb<u8> = Rgb([255, 0, 0]);
+
+// 3x5 bitmaps of the decimal digits, one row per byte, most significant of
+// the three low bits on the left.
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+/// Draws the outlines of accepted and rejected blobs over the source image,
+/// optionally writing each accepted blob's ID to the right of it.
+pub fn overlay(img: &DynamicImage, segmentation: &Segmentation, ids: bool) -> RgbImage {
+    let mut out = img.to_rgb8();
+    for (blob, _) in &segmentation.rejected {
+        for pt in blob.outline() {
+            out[pt] = REJECTED;
+        }
+    }
+    for (i, blob) in segmentation.blobs.iter().enumerate() {
+        for pt in blob.outline() {
+            out[pt] = ACCEPTED;
+        }
+        if ids {
+            let x = blob.bbox.x + blob.bbox.width + 1;
+            draw_number(&mut out, x, blob.bbox.y, i + 1, ACCEPTED);
+        }
+    }
+    out
+}
+
+fn draw_number(out: &mut RgbImage, x: u32, y: u32, n: usize, color: Rgb<u8>) {
+    for (i, digit) in n.to_string().bytes().enumerate() {
+        let glyph = DIGITS[(digit - b'0') as usize];
+        for (dy, row) in glyph.into_iter().enumerate() {
+            for dx in 0..3 {
+                let (px, py) = (x + i as u32 * 4 + dx, y + dy as u32);
+                if row & (0b100 >> dx) != 0 && px < out.width() && py < out.height() {
+                    out[(px, py)] = color;
+                }
+            }
+        }
+    }
+}
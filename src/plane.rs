@@ -0,0 +1,314 @@
+use image::{DynamicImage, ImageBuffer, Luma};
+use std::{error, fmt, io, str};
+use tiff::{
+    decoder::{Decoder, DecodingResult},
+    encoder::{colortype, TiffEncoder},
+    ColorType,
+};
+
+/// Sample type of the decoded input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Depth {
+    U8,
+    U16,
+    F32,
+}
+
+impl Depth {
+    /// The level of a fully saturated sample: white for integer samples and
+    /// 1 for floating point ones.
+    pub fn full_scale(self) -> f64 {
+        match self {
+            Depth::U8 => u8::MAX as f64,
+            Depth::U16 => u16::MAX as f64,
+            Depth::F32 => 1.0,
+        }
+    }
+
+    /// Formats a mean intensity: to three decimals for integer samples and
+    /// with the precision of a sample for floating point ones.
+    pub(crate) fn format_mean(self, mean: f64) -> String {
+        match self {
+            Depth::U8 | Depth::U16 => format!("{mean:.3}"),
+            Depth::F32 => format!("{}", mean as f32),
+        }
+    }
+}
+
+/// Formats an intensity level. Levels come from single precision samples,
+/// so they are printed as such.
+pub fn format_level(level: f64) -> String {
+    (level as f32).to_string()
+}
+
+/// Units of the intensity levels given in the segmentation parameters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Units {
+    /// Sample values of the input, e.g. 0 to 65535 for 16 bit images.
+    #[default]
+    Native,
+    /// Fractions of the full scale of the input's sample type.
+    Normalized,
+}
+
+impl Units {
+    /// Converts a level given in these units to native units.
+    pub fn to_native(self, level: f64, depth: Depth) -> f64 {
+        match self {
+            Units::Native => level,
+            Units::Normalized => level * depth.full_scale(),
+        }
+    }
+}
+
+impl str::FromStr for Units {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "native" => Ok(Units::Native),
+            "normalized" => Ok(Units::Normalized),
+            _ => Err(format!(
+                "unknown units {s:?} (expected native or normalized)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Units {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Units::Native => "native",
+            Units::Normalized => "normalized",
+        })
+    }
+}
+
+/// A grayscale image holding the samples of its input in native units,
+/// whatever their original type.
+#[derive(Clone, Debug)]
+pub struct Plane {
+    pub depth: Depth,
+    pub image: ImageBuffer<Luma<f32>, Vec<f32>>,
+}
+
+impl Plane {
+    pub fn new(depth: Depth, width: u32, height: u32, samples: Vec<f32>) -> Option<Plane> {
+        let image = ImageBuffer::from_raw(width, height, samples)?;
+        Some(Plane { depth, image })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.image.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.image.height()
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        self.image.as_raw()
+    }
+
+    pub fn samples_mut(&mut self) -> &mut [f32] {
+        &mut self.image
+    }
+
+    /// Mirrors every sample around half of the full scale, turning bright
+    /// objects into dark ones.
+    pub fn invert(&mut self) {
+        let full = self.depth.full_scale() as f32;
+        for v in self.samples_mut() {
+            *v = full - *v;
+        }
+    }
+
+    /// Converts a decoded image to grayscale, keeping 16 bit and floating
+    /// point samples at full precision.
+    pub fn from_dynamic(img: &DynamicImage) -> Plane {
+        let (width, height) = (img.width(), img.height());
+        let (depth, samples) = match img {
+            DynamicImage::ImageLuma16(_)
+            | DynamicImage::ImageLumaA16(_)
+            | DynamicImage::ImageRgb16(_)
+            | DynamicImage::ImageRgba16(_) => (
+                Depth::U16,
+                img.grayscale()
+                    .into_luma16()
+                    .into_raw()
+                    .into_iter()
+                    .map(f32::from)
+                    .collect(),
+            ),
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => (
+                Depth::F32,
+                // The gray value is replicated into every color channel.
+                img.grayscale()
+                    .into_rgb32f()
+                    .pixels()
+                    .map(|px| px.0[0])
+                    .collect(),
+            ),
+            _ => (
+                Depth::U8,
+                img.grayscale()
+                    .into_luma8()
+                    .into_raw()
+                    .into_iter()
+                    .map(f32::from)
+                    .collect(),
+            ),
+        };
+        Plane {
+            depth,
+            image: ImageBuffer::from_raw(width, height, samples)
+                .expect("grayscale conversion keeps the dimensions"),
+        }
+    }
+
+    /// Converts back to an image of the original sample type, rounding and
+    /// clamping integer samples.
+    pub fn to_dynamic(&self) -> DynamicImage {
+        let (width, height) = (self.width(), self.height());
+        let samples = self.samples().iter();
+        match self.depth {
+            Depth::U8 => DynamicImage::ImageLuma8(
+                ImageBuffer::from_raw(
+                    width,
+                    height,
+                    samples
+                        .map(|&v| v.round().clamp(0.0, 255.0) as u8)
+                        .collect(),
+                )
+                .expect("buffer matches the dimensions"),
+            ),
+            Depth::U16 => DynamicImage::ImageLuma16(
+                ImageBuffer::from_raw(
+                    width,
+                    height,
+                    samples
+                        .map(|&v| v.round().clamp(0.0, 65535.0) as u16)
+                        .collect(),
+                )
+                .expect("buffer matches the dimensions"),
+            ),
+            Depth::F32 => DynamicImage::ImageRgb32F(
+                ImageBuffer::from_raw(width, height, samples.flat_map(|&v| [v; 3]).collect())
+                    .expect("buffer matches the dimensions"),
+            ),
+        }
+    }
+
+    /// Writes the plane as a single-page grayscale TIFF of its sample type.
+    pub fn write_tiff(
+        &self,
+        out: impl io::Write + io::Seek,
+    ) -> Result<(), Box<dyn Send + Sync + error::Error>> {
+        let (width, height) = (self.width(), self.height());
+        let mut encoder = TiffEncoder::new(out)?;
+        match self.to_dynamic() {
+            DynamicImage::ImageLuma8(img) => {
+                encoder.write_image::<colortype::Gray8>(width, height, img.as_raw())?
+            }
+            DynamicImage::ImageLuma16(img) => {
+                encoder.write_image::<colortype::Gray16>(width, height, img.as_raw())?
+            }
+            _ => encoder.write_image::<colortype::Gray32Float>(width, height, self.samples())?,
+        }
+        Ok(())
+    }
+}
+
+/// A decoded page of a TIFF file.
+pub enum TiffPage {
+    /// A grayscale page at the bit depth of its samples.
+    Gray(Plane),
+    /// A color page, or a grayscale one with alpha, for a
+    /// [`Channel`](crate::Channel) to reduce to a plane.
+    Color(DynamicImage),
+}
+
+/// Reads every page of a TIFF file with 8 or 16 bit unsigned or 32 bit
+/// floating point samples. Returns `None` for a single page of any other
+/// color type so that the caller can fall back to a generic decoder, and an
+/// error if a page of a stack has one.
+pub fn read_tiff_stack(
+    input: impl io::Read + io::Seek,
+) -> Result<Option<Vec<TiffPage>>, Box<dyn Send + Sync + error::Error>> {
+    let mut decoder = Decoder::new(input)?;
+    let mut pages = Vec::new();
+    loop {
+        match read_page(&mut decoder)? {
+            Some(page) => pages.push(page),
+            None if pages.is_empty() && !decoder.more_images() => return Ok(None),
+            None => {
+                return Err(format!(
+                    "Page {} of the TIFF stack has unsupported color type {:?}",
+                    pages.len(),
+                    decoder.colortype()?
+                )
+                .into())
+            }
+        }
+        if !decoder.more_images() {
+            return Ok(Some(pages));
+        }
+        decoder.next_image()?;
+    }
+}
+
+/// Decodes the current page of a TIFF if it has a supported color and
+/// sample type.
+fn read_page<R: io::Read + io::Seek>(
+    decoder: &mut Decoder<R>,
+) -> Result<Option<TiffPage>, Box<dyn Send + Sync + error::Error>> {
+    let colortype = decoder.colortype()?;
+    if !matches!(
+        colortype,
+        ColorType::Gray(8 | 16 | 32)
+            | ColorType::GrayA(8 | 16)
+            | ColorType::RGB(8 | 16 | 32)
+            | ColorType::RGBA(8 | 16 | 32)
+    ) {
+        return Ok(None);
+    }
+    let (width, height) = decoder.dimensions()?;
+    let gray = |depth, samples| Plane::new(depth, width, height, samples).map(TiffPage::Gray);
+    let color = |img: Option<DynamicImage>| img.map(TiffPage::Color);
+    let page = match (colortype, decoder.read_image()?) {
+        (ColorType::Gray(_), DecodingResult::U8(v)) => {
+            gray(Depth::U8, v.into_iter().map(f32::from).collect())
+        }
+        (ColorType::Gray(_), DecodingResult::U16(v)) => {
+            gray(Depth::U16, v.into_iter().map(f32::from).collect())
+        }
+        (ColorType::Gray(_), DecodingResult::F32(v)) => gray(Depth::F32, v),
+        (ColorType::GrayA(_), DecodingResult::U8(v)) => {
+            color(ImageBuffer::from_raw(width, height, v).map(DynamicImage::ImageLumaA8))
+        }
+        (ColorType::GrayA(_), DecodingResult::U16(v)) => {
+            color(ImageBuffer::from_raw(width, height, v).map(DynamicImage::ImageLumaA16))
+        }
+        (ColorType::RGB(_), DecodingResult::U8(v)) => {
+            color(ImageBuffer::from_raw(width, height, v).map(DynamicImage::ImageRgb8))
+        }
+        (ColorType::RGB(_), DecodingResult::U16(v)) => {
+            color(ImageBuffer::from_raw(width, height, v).map(DynamicImage::ImageRgb16))
+        }
+        (ColorType::RGB(_), DecodingResult::F32(v)) => {
+            color(ImageBuffer::from_raw(width, height, v).map(DynamicImage::ImageRgb32F))
+        }
+        (ColorType::RGBA(_), DecodingResult::U8(v)) => {
+            color(ImageBuffer::from_raw(width, height, v).map(DynamicImage::ImageRgba8))
+        }
+        (ColorType::RGBA(_), DecodingResult::U16(v)) => {
+            color(ImageBuffer::from_raw(width, height, v).map(DynamicImage::ImageRgba16))
+        }
+        (ColorType::RGBA(_), DecodingResult::F32(v)) => {
+            color(ImageBuffer::from_raw(width, height, v).map(DynamicImage::ImageRgba32F))
+        }
+        _ => return Ok(None),
+    };
+    page.map(Some)
+        .ok_or_else(|| "TIFF page does not match its dimensions".into())
+}
@@ -0,0 +1,215 @@
+use crate::{overlay, Segmentation};
+use image::{imageops, DynamicImage, ImageOutputFormat, ImageResult, RgbImage};
+use std::io;
+
+/// Longest side of the thumbnails, in pixels.
+pub const THUMBNAIL_SIZE: u32 = 192;
+/// Number of bars of the size histograms.
+const BINS: usize = 20;
+
+/// What the report shows of one processed file.
+pub struct ReportImage {
+    pub file_name: String,
+    pub frames: usize,
+    /// Areas of the accepted blobs of every frame.
+    pub areas: Vec<usize>,
+    pub rejected: usize,
+    input: Vec<u8>,
+    overlay: Vec<u8>,
+}
+
+impl ReportImage {
+    /// Summarises the segmentations of the frames of a file, with thumbnails
+    /// of the first frame and of its overlay.
+    pub fn new(
+        file_name: String,
+        img: &DynamicImage,
+        frames: &[Segmentation],
+    ) -> ImageResult<ReportImage> {
+        let thumbnail = |img: &RgbImage| {
+            let scale = THUMBNAIL_SIZE as f64 / img.width().max(img.height()) as f64;
+            let (w, h) = if scale < 1.0 {
+                (
+                    ((img.width() as f64 * scale) as u32).max(1),
+                    ((img.height() as f64 * scale) as u32).max(1),
+                )
+            } else {
+                img.dimensions()
+            };
+            let mut png = io::Cursor::new(Vec::new());
+            DynamicImage::ImageRgb8(imageops::thumbnail(img, w, h))
+                .write_to(&mut png, ImageOutputFormat::Png)?;
+            Ok::<_, image::ImageError>(png.into_inner())
+        };
+        let (input, overlay) = match frames.first() {
+            Some(first) => (
+                thumbnail(&img.to_rgb8())?,
+                thumbnail(&overlay::overlay(img, first, false))?,
+            ),
+            None => (Vec::new(), Vec::new()),
+        };
+        Ok(ReportImage {
+            file_name,
+            frames: frames.len(),
+            areas: frames
+                .iter()
+                .flat_map(|frame| frame.blobs.iter().map(|blob| blob.area))
+                .collect(),
+            rejected: frames.iter().map(|frame| frame.rejected.len()).sum(),
+            input,
+            overlay,
+        })
+    }
+}
+
+/// Writes a self-contained HTML page with a table of the processed files,
+/// their thumbnails and size histograms, followed by the errors of the run.
+pub fn write(
+    out: &mut impl io::Write,
+    images: &[&ReportImage],
+    errors: &[String],
+) -> io::Result<()> {
+    let blobs: usize = images.iter().map(|image| image.areas.len()).sum();
+    let total_area: usize = images.iter().flat_map(|image| &image.areas).sum();
+    // All histograms share the bins of the batch so that they compare.
+    let max_area = images
+        .iter()
+        .flat_map(|image| &image.areas)
+        .copied()
+        .max()
+        .unwrap_or(0);
+    writeln!(
+        out,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Segmentation report</title>\n<style>\
+         body{{font-family:sans-serif;margin:2em}}table{{border-collapse:collapse}}\
+         th,td{{border:1px solid #ccc;padding:4px 8px;text-align:right;vertical-align:middle}}\
+         td.name{{text-align:left}}.errors{{color:#b00}}</style></head><body>"
+    )?;
+    writeln!(out, "<h1>Segmentation report</h1>")?;
+    writeln!(
+        out,
+        "<p>{} images, {} blobs, mean area {}, {} errors</p>",
+        images.len(),
+        blobs,
+        mean(total_area, blobs),
+        errors.len()
+    )?;
+    writeln!(
+        out,
+        "<h2>Blob sizes</h2>\n{}",
+        histogram(
+            images.iter().flat_map(|image| &image.areas),
+            max_area,
+            480,
+            120
+        )
+    )?;
+    writeln!(out, "<p>Area from 0 to {max_area} pixels</p>")?;
+    writeln!(
+        out,
+        "<h2>Images</h2>\n<table>\n<tr><th>Image</th><th>Frames</th><th>Blobs</th><th>Rejected</th>\
+         <th>Mean area</th><th>Sizes</th><th>Input</th><th>Overlay</th></tr>"
+    )?;
+    for image in images {
+        writeln!(
+            out,
+            "<tr><td class=\"name\">{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>\
+             <td>{}</td><td>{}</td></tr>",
+            escape(&image.file_name),
+            image.frames,
+            image.areas.len(),
+            image.rejected,
+            mean(image.areas.iter().sum(), image.areas.len()),
+            histogram(&image.areas, max_area, 120, 40),
+            png(&image.input),
+            png(&image.overlay)
+        )?;
+    }
+    writeln!(out, "</table>")?;
+    if !errors.is_empty() {
+        writeln!(out, "<h2>Errors</h2>\n<ul class=\"errors\">")?;
+        for error in errors {
+            writeln!(out, "<li>{}</li>", escape(error))?;
+        }
+        writeln!(out, "</ul>")?;
+    }
+    writeln!(out, "</body></html>")
+}
+
+fn mean(total: usize, count: usize) -> String {
+    if count == 0 {
+        "-".to_owned()
+    } else {
+        format!("{:.1}", total as f64 / count as f64)
+    }
+}
+
+/// Renders the counts of areas in [`BINS`] equal bins up to `max_area` as an
+/// inline SVG bar chart.
+fn histogram<'a>(
+    areas: impl IntoIterator<Item = &'a usize>,
+    max_area: usize,
+    width: u32,
+    height: u32,
+) -> String {
+    let mut counts = [0usize; BINS];
+    for &area in areas {
+        counts[(area * BINS / (max_area + 1)).min(BINS - 1)] += 1;
+    }
+    let top = counts.iter().copied().max().unwrap_or(0).max(1);
+    let bar = width as f64 / BINS as f64;
+    let bars: String = counts
+        .iter()
+        .enumerate()
+        .filter(|(_, &n)| n > 0)
+        .map(|(i, &n)| {
+            let h = n as f64 / top as f64 * height as f64;
+            format!(
+                "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"#4a7\"><title>{n}</title></rect>",
+                i as f64 * bar,
+                height as f64 - h,
+                bar - 1.0,
+                h
+            )
+        })
+        .collect();
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\">\
+         <rect width=\"{width}\" height=\"{height}\" fill=\"#f4f4f4\"/>{bars}</svg>"
+    )
+}
+
+/// Embeds PNG data as an image, or nothing for an empty thumbnail.
+fn png(data: &[u8]) -> String {
+    if data.is_empty() {
+        return String::new();
+    }
+    format!("<img src=\"data:image/png;base64,{}\">", base64(data))
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Escapes text for use in HTML content and attributes.
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,497 @@
+use crate::{
+    background::{self, Background},
+    blob::{Blob, Connectivity},
+    ccl,
+    channel::Channel,
+    denoise::Denoise,
+    morphology::{self, Element},
+    plane::{Depth, Plane, Units},
+    threshold::{self, Histogram, Polarity, Threshold},
+    watershed,
+};
+use image::{imageops, DynamicImage, GrayImage};
+use std::{f64, fmt};
+
+/// Tunable knobs of the segmentation pipeline.
+#[derive(Clone, Debug)]
+pub struct SegmentationParams {
+    /// What of a color image is segmented and measured. Grayscale inputs
+    /// count as gray pixels.
+    pub channel: Channel,
+    /// Whether to look for dark or bright objects. Bright objects are found
+    /// on the inverted image, so all levels below then count down from white.
+    pub polarity: Polarity,
+    /// Smoothing applied before thresholding. Blobs are still measured on
+    /// the unsmoothed image.
+    pub denoise: Denoise,
+    /// Background estimate subtracted before thresholding. The background
+    /// ends up white for dark objects and black for bright ones, so levels
+    /// count from there rather than from the raw image.
+    pub background: Background,
+    /// Whether to use `max_black`/`max_gray` or derive the levels per image.
+    pub threshold: Threshold,
+    /// Units of `max_black`, `max_gray`, the offsets, `local_c` and the
+    /// range sigma of the bilateral filter.
+    pub units: Units,
+    /// Pixels at or below this level start a new blob. At most `max_gray`.
+    pub max_black: f64,
+    /// Pixels at or below this level extend a blob they touch.
+    pub max_gray: f64,
+    /// Offset of the seed level from an automatically chosen threshold. At
+    /// most `grow_offset`.
+    pub seed_offset: f64,
+    /// Offset of the grow level from an automatically chosen threshold.
+    pub grow_offset: f64,
+    /// Which neighbours a blob grows into.
+    pub connectivity: Connectivity,
+    /// Side length of the neighbourhood used by local thresholds.
+    pub window: u32,
+    /// Weight of the local standard deviation for Sauvola and Niblack.
+    pub local_k: f64,
+    /// Constant subtracted from the local mean by mean-minus-C.
+    pub local_c: f64,
+    /// Radius of the binary opening applied to the thresholded masks, or 0
+    /// to skip it.
+    pub open_radius: u32,
+    /// Radius of the binary closing applied after the opening, or 0 to skip
+    /// it.
+    pub close_radius: u32,
+    /// Structuring element of the opening and closing.
+    pub element: Element,
+    /// Whether to fill holes in the masks, such as the bright centres of
+    /// dots with a reflection.
+    pub fill_holes: bool,
+    /// Components with fewer pixels are dropped before measurement and do
+    /// not show up among the rejected blobs.
+    pub discard_below: usize,
+    /// Whether to split touching blobs along the watershed of their distance
+    /// transform before they are measured and filtered.
+    pub split: bool,
+    /// Closest two distance transform peaks may lie and still seed separate
+    /// blobs, in pixels.
+    pub split_min_distance: f64,
+    /// Smallest accepted blob, in pixels.
+    pub min_area: usize,
+    /// Largest accepted blob, in pixels.
+    pub max_area: usize,
+    /// How far past the radius of a disc of the same area a blob pixel may
+    /// lie before the blob is considered not round.
+    pub radius_tolerance: f64,
+    /// Smallest accepted circularity, from 0 to 1 for a disc.
+    pub min_circularity: f64,
+    /// Largest accepted circularity, from 0 to 1 for a disc.
+    pub max_circularity: f64,
+    /// Smallest accepted solidity, from 0 to 1 for a convex blob.
+    pub min_solidity: f64,
+    /// Largest accepted solidity, from 0 to 1 for a convex blob.
+    pub max_solidity: f64,
+    /// Smallest accepted eccentricity, from 0 for a disc to 1 for a line.
+    pub min_eccentricity: f64,
+    /// Largest accepted eccentricity, from 0 for a disc to 1 for a line.
+    pub max_eccentricity: f64,
+    /// Smallest accepted aspect ratio, from 1 for a disc upwards.
+    pub min_aspect_ratio: f64,
+    /// Largest accepted aspect ratio, from 1 for a disc upwards.
+    pub max_aspect_ratio: f64,
+}
+
+impl Default for SegmentationParams {
+    fn default() -> Self {
+        SegmentationParams {
+            channel: Channel::Luma,
+            polarity: Polarity::Dark,
+            denoise: Denoise::None,
+            background: Background::None,
+            threshold: Threshold::Fixed,
+            units: Units::Native,
+            max_black: 40.0,
+            max_gray: 60.0,
+            seed_offset: 0.0,
+            grow_offset: 20.0,
+            connectivity: Connectivity::Four,
+            window: 51,
+            local_k: 0.2,
+            local_c: 10.0,
+            open_radius: 0,
+            close_radius: 0,
+            element: Element::Disk,
+            fill_holes: false,
+            discard_below: 0,
+            split: false,
+            split_min_distance: 5.0,
+            min_area: 3,
+            max_area: 10000,
+            radius_tolerance: 1.5,
+            min_circularity: 0.0,
+            max_circularity: 1.0,
+            min_solidity: 0.0,
+            max_solidity: 1.0,
+            min_eccentricity: 0.0,
+            max_eccentricity: 1.0,
+            min_aspect_ratio: 1.0,
+            max_aspect_ratio: f64::INFINITY,
+        }
+    }
+}
+
+impl SegmentationParams {
+    /// Checks that the seed levels lie within the grow levels, so that every
+    /// pixel that starts a blob can also extend one.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_black > self.max_gray {
+            return Err(format!(
+                "max_black ({}) must not exceed max_gray ({})",
+                self.max_black, self.max_gray
+            ));
+        }
+        if self.seed_offset > self.grow_offset {
+            return Err(format!(
+                "seed_offset ({}) must not exceed grow_offset ({})",
+                self.seed_offset, self.grow_offset
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Why a candidate blob was not accepted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    TooSmall,
+    TooLarge,
+    NotRound,
+    /// Circularity outside the configured range.
+    Circularity,
+    /// Solidity outside the configured range.
+    Solidity,
+    /// Eccentricity outside the configured range.
+    Eccentricity,
+    /// Aspect ratio outside the configured range.
+    AspectRatio,
+}
+
+impl Rejection {
+    pub const ALL: [Rejection; 7] = [
+        Rejection::TooSmall,
+        Rejection::TooLarge,
+        Rejection::NotRound,
+        Rejection::Circularity,
+        Rejection::Solidity,
+        Rejection::Eccentricity,
+        Rejection::AspectRatio,
+    ];
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Rejection::TooSmall => "too_small",
+            Rejection::TooLarge => "too_large",
+            Rejection::NotRound => "not_round",
+            Rejection::Circularity => "circularity",
+            Rejection::Solidity => "solidity",
+            Rejection::Eccentricity => "eccentricity",
+            Rejection::AspectRatio => "aspect_ratio",
+        })
+    }
+}
+
+/// The intensity levels the flood fill ran with, in native units of the
+/// input image.
+/// For bright objects pixels pass at or above the levels rather than at or
+/// below them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Levels {
+    /// The same levels everywhere, with the automatically selected threshold
+    /// they were derived from, if any.
+    Global {
+        threshold: Option<f64>,
+        seed: f64,
+        grow: f64,
+    },
+    /// Per-pixel levels; `mean` is the average local threshold.
+    Local { mean: f64 },
+}
+
+impl Levels {
+    /// Mirrors the levels around half of `full_scale`.
+    fn inverted(self, full_scale: f64) -> Levels {
+        match self {
+            Levels::Global {
+                threshold,
+                seed,
+                grow,
+            } => Levels::Global {
+                threshold: threshold.map(|t| full_scale - t),
+                seed: full_scale - seed,
+                grow: full_scale - grow,
+            },
+            Levels::Local { mean } => Levels::Local {
+                mean: full_scale - mean,
+            },
+        }
+    }
+}
+
+/// The outcome of segmenting a single image.
+#[derive(Clone, Debug)]
+pub struct Segmentation {
+    pub width: u32,
+    pub height: u32,
+    /// Sample type of the segmented image, which sets the units of the
+    /// levels and intensities.
+    pub depth: Depth,
+    pub polarity: Polarity,
+    pub levels: Levels,
+    /// The accepted blobs. A blob's ID is its index plus one.
+    pub blobs: Vec<Blob>,
+    pub rejected: Vec<(Blob, Rejection)>,
+    /// The subtracted background, if any, in the orientation of the input.
+    pub background: Option<Plane>,
+}
+
+impl Segmentation {
+    /// Renders the accepted blobs as black on a white background.
+    pub fn mask(&self) -> GrayImage {
+        let mut out = GrayImage::new(self.width, self.height);
+        for blob in &self.blobs {
+            for &pt in &blob.pixels {
+                out[pt] = [255].into();
+            }
+        }
+        imageops::colorops::invert(&mut out);
+        out
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Segmenter {
+    params: SegmentationParams,
+}
+
+impl Segmenter {
+    pub fn new(params: SegmentationParams) -> Segmenter {
+        Segmenter { params }
+    }
+
+    pub fn params(&self) -> &SegmentationParams {
+        &self.params
+    }
+
+    /// Segments a decoded image of any color type by first reducing it to
+    /// the plane of the configured [`Channel`], at the bit depth of the
+    /// input.
+    pub fn segment(&self, img: &DynamicImage) -> Segmentation {
+        self.segment_plane(&self.params.channel.extract(img))
+    }
+
+    pub fn segment_gray(&self, img: &GrayImage) -> Segmentation {
+        self.segment_plane(
+            &self
+                .params
+                .channel
+                .extract(&DynamicImage::ImageLuma8(img.clone())),
+        )
+    }
+
+    /// Picks the seed and grow levels for an image and marks the pixels
+    /// passing each of them.
+    fn masks(&self, img: &Plane) -> (Levels, Vec<bool>, Vec<bool>) {
+        let native = |level| self.params.units.to_native(level, img.depth);
+        let global = |threshold, seed: f64, grow: f64| {
+            let (seeds, grows) = img
+                .samples()
+                .iter()
+                .map(|&px| (px as f64 <= seed, px as f64 <= grow))
+                .unzip();
+            (
+                Levels::Global {
+                    threshold,
+                    seed,
+                    grow,
+                },
+                seeds,
+                grows,
+            )
+        };
+        match self.params.threshold {
+            Threshold::Fixed => global(
+                None,
+                native(self.params.max_black),
+                native(self.params.max_gray),
+            ),
+            Threshold::Auto(method) => {
+                let hist = Histogram::new(img);
+                let t = hist.level(method.apply(&hist.counts));
+                let offset = |d| match img.depth {
+                    Depth::U8 | Depth::U16 => (t + native(d)).clamp(0.0, img.depth.full_scale()),
+                    Depth::F32 => t + native(d),
+                };
+                global(
+                    Some(t),
+                    offset(self.params.seed_offset),
+                    offset(self.params.grow_offset),
+                )
+            }
+            Threshold::Local(method) => {
+                let ts = threshold::local_thresholds(
+                    img,
+                    method,
+                    self.params.window,
+                    self.params.local_k,
+                    native(self.params.local_c),
+                );
+                let mean = ts.iter().sum::<f64>() / ts.len().max(1) as f64;
+                let (seed, grow) = (
+                    native(self.params.seed_offset),
+                    native(self.params.grow_offset),
+                );
+                let (seeds, grows) = img
+                    .samples()
+                    .iter()
+                    .zip(&ts)
+                    .map(|(&px, &t)| (px as f64 <= t + seed, px as f64 <= t + grow))
+                    .unzip();
+                (Levels::Local { mean }, seeds, grows)
+            }
+        }
+    }
+
+    /// Runs the configured opening, closing and hole filling over a mask.
+    /// Each step is increasing, so seeds stay within the grow mask.
+    fn clean(&self, mut mask: Vec<bool>, width: u32) -> Vec<bool> {
+        let p = &self.params;
+        if p.open_radius > 0 {
+            mask = morphology::open(&mask, width, p.element, p.open_radius);
+        }
+        if p.close_radius > 0 {
+            mask = morphology::close(&mask, width, p.element, p.close_radius);
+        }
+        if p.fill_holes {
+            mask = morphology::fill_holes(&mask, width, p.connectivity);
+        }
+        mask
+    }
+
+    /// Segments a grayscale image in the native units of its samples.
+    /// Blobs are measured against `img` itself, before any smoothing or
+    /// background subtraction.
+    pub fn segment_plane(&self, img: &Plane) -> Segmentation {
+        let smoothed = self.params.denoise.apply(img, self.params.units);
+        let polarity = self
+            .params
+            .polarity
+            .resolve(&Histogram::new(&smoothed).counts);
+        let bright = polarity == Polarity::Bright;
+        let mut work = smoothed;
+        if bright {
+            work.to_mut().invert();
+        }
+        let mut background = self.params.background.estimate(&work);
+        if let Some(bg) = &background {
+            background::subtract(work.to_mut(), bg);
+        }
+        let (mut levels, seeds, grows) = self.masks(&work);
+        if bright {
+            levels = levels.inverted(img.depth.full_scale());
+            if let Some(bg) = &mut background {
+                bg.invert();
+            }
+        }
+        let (seeds, grows) = (
+            self.clean(seeds, img.width()),
+            self.clean(grows, img.width()),
+        );
+        let mut components = ccl::hysteresis(
+            img.width(),
+            img.height(),
+            &seeds,
+            &grows,
+            self.params.connectivity,
+        );
+        if self.params.split {
+            components = components
+                .into_iter()
+                .flat_map(|pixels| {
+                    watershed::split(
+                        pixels,
+                        self.params.connectivity,
+                        self.params.split_min_distance,
+                    )
+                })
+                .collect();
+        }
+        let candidates = components
+            .into_iter()
+            .filter(|pixels| pixels.len() >= self.params.discard_below)
+            .map(|pixels| Blob::new(pixels, img, self.params.connectivity));
+        let mut blobs = Vec::new();
+        let mut rejected = Vec::new();
+        for blob in candidates {
+            match self.check(&blob) {
+                Some(reason) => rejected.push((blob, reason)),
+                None => blobs.push(blob),
+            }
+        }
+        Segmentation {
+            width: img.width(),
+            height: img.height(),
+            depth: img.depth,
+            polarity,
+            levels,
+            blobs,
+            rejected,
+            background,
+        }
+    }
+
+    fn check(&self, blob: &Blob) -> Option<Rejection> {
+        if blob.area < self.params.min_area {
+            return Some(Rejection::TooSmall);
+        }
+        if blob.area > self.params.max_area {
+            return Some(Rejection::TooLarge);
+        }
+        let (cx, cy) = blob.centroid;
+        let expected_radius = (blob.area as f64 / f64::consts::PI).sqrt();
+        let allowed_radius = expected_radius * self.params.radius_tolerance;
+        if blob
+            .pixels
+            .iter()
+            .copied()
+            .any(|(x, y)| (x as f64 - cx).hypot(y as f64 - cy) > allowed_radius)
+        {
+            return Some(Rejection::NotRound);
+        }
+        let p = &self.params;
+        [
+            (
+                blob.circularity,
+                p.min_circularity,
+                p.max_circularity,
+                Rejection::Circularity,
+            ),
+            (
+                blob.solidity,
+                p.min_solidity,
+                p.max_solidity,
+                Rejection::Solidity,
+            ),
+            (
+                blob.eccentricity,
+                p.min_eccentricity,
+                p.max_eccentricity,
+                Rejection::Eccentricity,
+            ),
+            (
+                blob.aspect_ratio,
+                p.min_aspect_ratio,
+                p.max_aspect_ratio,
+                Rejection::AspectRatio,
+            ),
+        ]
+        .into_iter()
+        .find(|&(value, min, max, _)| !(min..=max).contains(&value))
+        .map(|(_, _, _, reason)| reason)
+    }
+}
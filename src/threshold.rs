@@ -0,0 +1,389 @@
+use crate::plane::{Depth, Plane};
+use std::{fmt, str};
+
+/// Global threshold selection algorithms working on an intensity histogram.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThresholdMethod {
+    Otsu,
+    Triangle,
+    Li,
+    Mean,
+}
+
+impl ThresholdMethod {
+    /// Returns the highest histogram bin that belongs to the dark class.
+    pub fn apply(self, hist: &[u64]) -> usize {
+        let total: u64 = hist.iter().sum();
+        if total == 0 {
+            return 0;
+        }
+        match self {
+            ThresholdMethod::Otsu => otsu(hist, total),
+            ThresholdMethod::Triangle => triangle(hist),
+            ThresholdMethod::Li => li(hist, total),
+            ThresholdMethod::Mean => mean(hist.iter().copied().enumerate(), total) as usize,
+        }
+    }
+}
+
+impl str::FromStr for ThresholdMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "otsu" => Ok(ThresholdMethod::Otsu),
+            "triangle" => Ok(ThresholdMethod::Triangle),
+            "li" => Ok(ThresholdMethod::Li),
+            "mean" => Ok(ThresholdMethod::Mean),
+            _ => Err(format!(
+                "unknown threshold method {s:?} (expected otsu, triangle, li or mean)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ThresholdMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ThresholdMethod::Otsu => "otsu",
+            ThresholdMethod::Triangle => "triangle",
+            ThresholdMethod::Li => "li",
+            ThresholdMethod::Mean => "mean",
+        })
+    }
+}
+
+/// Local threshold rules, evaluated over a square window around each pixel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LocalMethod {
+    /// `mean * (1 + k * (stddev / 128 - 1))`
+    Sauvola,
+    /// `mean - k * stddev`
+    Niblack,
+    /// `mean - c`
+    MeanC,
+}
+
+impl str::FromStr for LocalMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sauvola" => Ok(LocalMethod::Sauvola),
+            "niblack" => Ok(LocalMethod::Niblack),
+            "mean" => Ok(LocalMethod::MeanC),
+            _ => Err(format!(
+                "unknown local threshold method {s:?} (expected sauvola, niblack or mean)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LocalMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LocalMethod::Sauvola => "sauvola",
+            LocalMethod::Niblack => "niblack",
+            LocalMethod::MeanC => "mean",
+        })
+    }
+}
+
+/// How the seed and grow levels of the flood fill are chosen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Threshold {
+    /// Use the configured levels as is.
+    #[default]
+    Fixed,
+    /// Compute a level per image and offset both the seed and grow levels
+    /// from it.
+    Auto(ThresholdMethod),
+    /// Compute a threshold per pixel from its neighbourhood and offset both
+    /// the seed and grow levels from it.
+    Local(LocalMethod),
+}
+
+impl str::FromStr for Threshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "fixed" => Ok(Threshold::Fixed),
+            Some(("auto", method)) => Ok(Threshold::Auto(method.parse()?)),
+            Some(("local", method)) => Ok(Threshold::Local(method.parse()?)),
+            _ => Err(format!(
+                "unknown threshold {s:?} (expected fixed, auto:<method> or local:<method>)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Threshold::Fixed => f.write_str("fixed"),
+            Threshold::Auto(method) => write!(f, "auto:{method}"),
+            Threshold::Local(method) => write!(f, "local:{method}"),
+        }
+    }
+}
+
+/// Which side of the intensity range the objects of interest lie on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Polarity {
+    /// Dark objects on a light background.
+    #[default]
+    Dark,
+    /// Bright objects on a dark background.
+    Bright,
+    /// Decide per image from its histogram.
+    Auto,
+}
+
+impl Polarity {
+    /// Resolves [`Polarity::Auto`] by splitting the histogram with Otsu's
+    /// method and assuming the larger class is the background.
+    pub fn resolve(self, hist: &[u64]) -> Polarity {
+        match self {
+            Polarity::Auto => {
+                let t = ThresholdMethod::Otsu.apply(hist);
+                let below: u64 = hist[..=t].iter().sum();
+                let above: u64 = hist[t + 1..].iter().sum();
+                if below > above {
+                    Polarity::Bright
+                } else {
+                    Polarity::Dark
+                }
+            }
+            resolved => resolved,
+        }
+    }
+}
+
+impl str::FromStr for Polarity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dark" => Ok(Polarity::Dark),
+            "bright" => Ok(Polarity::Bright),
+            "auto" => Ok(Polarity::Auto),
+            _ => Err(format!(
+                "unknown polarity {s:?} (expected dark, bright or auto)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Polarity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Polarity::Dark => "dark",
+            Polarity::Bright => "bright",
+            Polarity::Auto => "auto",
+        })
+    }
+}
+
+/// Intensity histogram of a plane. Integer samples get one bin per level;
+/// floating point samples are spread over [`FLOAT_BINS`] bins between the
+/// smallest and largest finite sample.
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    pub counts: Vec<u64>,
+    /// Level of the lower edge of the first bin.
+    pub start: f64,
+    /// Width of a bin in levels.
+    pub step: f64,
+    depth: Depth,
+}
+
+pub const FLOAT_BINS: usize = 4096;
+
+impl Histogram {
+    pub fn new(plane: &Plane) -> Histogram {
+        let samples = plane.samples().iter().copied().filter(|v| v.is_finite());
+        let (start, step, bins) = match plane.depth {
+            Depth::U8 => (0.0, 1.0, 256),
+            Depth::U16 => (0.0, 1.0, 65536),
+            Depth::F32 => {
+                let (lo, hi) = samples
+                    .clone()
+                    .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), v| {
+                        (lo.min(v), hi.max(v))
+                    });
+                let (lo, hi) = if lo <= hi {
+                    (lo as f64, hi as f64)
+                } else {
+                    (0.0, 0.0)
+                };
+                let step = if hi > lo {
+                    (hi - lo) / FLOAT_BINS as f64
+                } else {
+                    1.0
+                };
+                (lo, step, FLOAT_BINS)
+            }
+        };
+        let mut counts = vec![0; bins];
+        for v in samples {
+            let bin = ((v as f64 - start) / step) as usize;
+            counts[bin.min(bins - 1)] += 1;
+        }
+        Histogram {
+            counts,
+            start,
+            step,
+            depth: plane.depth,
+        }
+    }
+
+    /// The highest level that falls into bin `bin` or below.
+    pub fn level(&self, bin: usize) -> f64 {
+        match self.depth {
+            Depth::U8 | Depth::U16 => self.start + bin as f64 * self.step,
+            Depth::F32 => self.start + (bin + 1) as f64 * self.step,
+        }
+    }
+}
+
+/// Computes a threshold for every pixel from the mean and standard deviation
+/// of the `window` x `window` square centred on it, clipped to the image.
+/// `c` is in native units; Sauvola's dynamic range of the standard deviation
+/// is 128 on the 8 bit scale and scaled to match for other sample types.
+pub fn local_thresholds(img: &Plane, method: LocalMethod, window: u32, k: f64, c: f64) -> Vec<f64> {
+    let (w, h) = (img.width() as usize, img.height() as usize);
+    // Summed-area tables of the intensities and their squares, padded with a
+    // leading row and column of zeros.
+    let mut sum = vec![0.0; (w + 1) * (h + 1)];
+    let mut sq = vec![0.0; (w + 1) * (h + 1)];
+    for y in 0..h {
+        let (mut row, mut row_sq) = (0.0, 0.0);
+        for x in 0..w {
+            let v = img.samples()[y * w + x] as f64;
+            row += v;
+            row_sq += v * v;
+            sum[(y + 1) * (w + 1) + x + 1] = sum[y * (w + 1) + x + 1] + row;
+            sq[(y + 1) * (w + 1) + x + 1] = sq[y * (w + 1) + x + 1] + row_sq;
+        }
+    }
+    let range = 128.0 * img.depth.full_scale() / u8::MAX as f64;
+    let r = (window / 2) as usize;
+    let mut out = Vec::with_capacity(w * h);
+    for y in 0..h {
+        let (y0, y1) = (y.saturating_sub(r), (y + r + 1).min(h));
+        for x in 0..w {
+            let (x0, x1) = (x.saturating_sub(r), (x + r + 1).min(w));
+            let area = |t: &[f64]| {
+                t[y1 * (w + 1) + x1] - t[y0 * (w + 1) + x1] - t[y1 * (w + 1) + x0]
+                    + t[y0 * (w + 1) + x0]
+            };
+            let n = ((y1 - y0) * (x1 - x0)) as f64;
+            let mean = area(&sum) / n;
+            let stddev = (area(&sq) / n - mean * mean).max(0.0).sqrt();
+            out.push(match method {
+                LocalMethod::Sauvola => mean * (1.0 + k * (stddev / range - 1.0)),
+                LocalMethod::Niblack => mean - k * stddev,
+                LocalMethod::MeanC => mean - c,
+            });
+        }
+    }
+    out
+}
+
+fn mean(bins: impl Iterator<Item = (usize, u64)>, total: u64) -> f64 {
+    bins.map(|(i, n)| i as f64 * n as f64).sum::<f64>() / total as f64
+}
+
+fn otsu(hist: &[u64], total: u64) -> usize {
+    let sum_all: f64 = hist
+        .iter()
+        .enumerate()
+        .map(|(i, &n)| i as f64 * n as f64)
+        .sum();
+    let (mut w0, mut sum0) = (0.0, 0.0);
+    let (mut best, mut best_var) = (0, f64::NEG_INFINITY);
+    for (i, &n) in hist.iter().enumerate() {
+        w0 += n as f64;
+        sum0 += i as f64 * n as f64;
+        let w1 = total as f64 - w0;
+        if w0 == 0.0 || w1 == 0.0 {
+            continue;
+        }
+        let (m0, m1) = (sum0 / w0, (sum_all - sum0) / w1);
+        let var = w0 * w1 * (m0 - m1) * (m0 - m1);
+        if var > best_var {
+            best_var = var;
+            best = i;
+        }
+    }
+    best
+}
+
+fn triangle(hist: &[u64]) -> usize {
+    let first = hist.iter().position(|&n| n > 0).unwrap_or(0);
+    let last = hist.iter().rposition(|&n| n > 0).unwrap_or(0);
+    let peak = (first..=last).max_by_key(|&i| hist[i]).unwrap_or(first);
+    // Draw the line from the peak to the end of the longer tail and pick the
+    // bin furthest below it.
+    let end = if peak - first > last - peak {
+        first
+    } else {
+        last
+    };
+    let (px, py) = (peak as f64, hist[peak] as f64);
+    let (ex, ey) = (end as f64, hist[end] as f64);
+    let (nx, ny) = (ey - py, px - ex);
+    let (lo, hi) = (peak.min(end), peak.max(end));
+    let split = (lo..=hi)
+        .max_by(|&a, &b| {
+            let da = nx * (a as f64 - px) + ny * (hist[a] as f64 - py);
+            let db = nx * (b as f64 - px) + ny * (hist[b] as f64 - py);
+            da.abs().total_cmp(&db.abs())
+        })
+        .unwrap_or(peak);
+    if end < peak {
+        split
+    } else {
+        split.saturating_sub(1)
+    }
+}
+
+fn li(hist: &[u64], total: u64) -> usize {
+    // Minimum cross entropy, iterated as in Li & Tam (1998) on intensities
+    // shifted so that the darkest populated bin sits at one.
+    let first = hist.iter().position(|&n| n > 0).unwrap_or(0);
+    let shifted = || {
+        hist.iter()
+            .copied()
+            .enumerate()
+            .skip(first)
+            .map(|(i, n)| (i - first + 1, n))
+    };
+    let mut t = mean(shifted(), total);
+    for _ in 0..1000 {
+        let (mut nb, mut sb, mut nf, mut sf) = (0.0, 0.0, 0.0, 0.0);
+        for (i, n) in shifted() {
+            if i as f64 > t {
+                nf += n as f64;
+                sf += i as f64 * n as f64;
+            } else {
+                nb += n as f64;
+                sb += i as f64 * n as f64;
+            }
+        }
+        if nb == 0.0 || nf == 0.0 {
+            break;
+        }
+        let (mb, mf) = (sb / nb, sf / nf);
+        let next = (mf - mb) / (mf.ln() - mb.ln());
+        if (next - t).abs() < 0.5 {
+            t = next;
+            break;
+        }
+        t = next;
+    }
+    (t.floor() as usize + first)
+        .saturating_sub(1)
+        .min(hist.len() - 1)
+}
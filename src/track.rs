@@ -0,0 +1,360 @@
+use std::{fmt, str};
+
+/// How the detections of a frame are matched to the tracks so far.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Linking {
+    /// Repeatedly links the closest remaining pair of track and detection.
+    #[default]
+    Nearest,
+    /// Links so that the summed displacement of all links and the cost of
+    /// leaving tracks and detections unlinked is minimal.
+    Hungarian,
+}
+
+impl str::FromStr for Linking {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Linking::Nearest),
+            "hungarian" => Ok(Linking::Hungarian),
+            _ => Err(format!(
+                "unknown linking {s:?} (expected nearest or hungarian)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Linking {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Linking::Nearest => "nearest",
+            Linking::Hungarian => "hungarian",
+        })
+    }
+}
+
+/// Tunable knobs of the frame to frame linking.
+#[derive(Clone, Debug)]
+pub struct TrackParams {
+    pub linking: Linking,
+    /// Farthest a detection may lie from the last position of a track and
+    /// still continue it, in pixels.
+    pub max_displacement: f64,
+    /// How many frames in a row a track may go undetected and still be
+    /// continued afterwards.
+    pub max_gap: usize,
+}
+
+impl TrackParams {
+    /// Checks that the maximum displacement is a positive, finite distance.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.max_displacement.is_finite() && self.max_displacement > 0.0) {
+            return Err(format!(
+                "max_displacement ({}) must be a positive, finite distance",
+                self.max_displacement
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for TrackParams {
+    fn default() -> Self {
+        TrackParams {
+            linking: Linking::Nearest,
+            max_displacement: 10.0,
+            max_gap: 0,
+        }
+    }
+}
+
+struct Track {
+    position: (f64, f64),
+    frame: usize,
+}
+
+/// Links the detections of consecutive frames into tracks. Returns the
+/// track ID of every detection, frame by frame, with IDs counting from 1 in
+/// order of their first detection.
+pub fn link(frames: &[Vec<(f64, f64)>], params: &TrackParams) -> Vec<Vec<usize>> {
+    let mut tracks: Vec<Track> = Vec::new();
+    frames
+        .iter()
+        .enumerate()
+        .map(|(frame, detections)| {
+            // Tracks that may still continue, and the admissible links to them.
+            let open: Vec<usize> = (0..tracks.len())
+                .filter(|&t| frame - tracks[t].frame <= params.max_gap + 1)
+                .collect();
+            let mut costs = vec![None; open.len() * detections.len()];
+            for (i, &t) in open.iter().enumerate() {
+                for (j, &(x, y)) in detections.iter().enumerate() {
+                    let (tx, ty) = tracks[t].position;
+                    let d = (x - tx).hypot(y - ty);
+                    if d <= params.max_displacement {
+                        costs[i * detections.len() + j] = Some(d);
+                    }
+                }
+            }
+            let matched = match params.linking {
+                Linking::Nearest => nearest(&costs, open.len(), detections.len()),
+                Linking::Hungarian => hungarian(
+                    &costs,
+                    open.len(),
+                    detections.len(),
+                    params.max_displacement,
+                ),
+            };
+            detections
+                .iter()
+                .zip(matched)
+                .map(|(&position, m)| {
+                    let t = m.map_or_else(
+                        || {
+                            tracks.push(Track { position, frame });
+                            tracks.len() - 1
+                        },
+                        |i| open[i],
+                    );
+                    tracks[t] = Track { position, frame };
+                    t + 1
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Greedy matching in order of increasing cost. Returns the matched row of
+/// every column.
+fn nearest(costs: &[Option<f64>], rows: usize, cols: usize) -> Vec<Option<usize>> {
+    let mut pairs: Vec<(f64, usize, usize)> = costs
+        .iter()
+        .enumerate()
+        .filter_map(|(k, c)| c.map(|c| (c, k / cols, k % cols)))
+        .collect();
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut row_used = vec![false; rows];
+    let mut matched = vec![None; cols];
+    for (_, i, j) in pairs {
+        if !row_used[i] && matched[j].is_none() {
+            row_used[i] = true;
+            matched[j] = Some(i);
+        }
+    }
+    matched
+}
+
+/// Minimum cost matching where leaving a row or column unmatched costs
+/// `unmatched`. Rows and columns only interact through admissible links, so
+/// every connected component of them is solved on its own. Returns the
+/// matched row of every column.
+fn hungarian(
+    costs: &[Option<f64>],
+    rows: usize,
+    cols: usize,
+    unmatched: f64,
+) -> Vec<Option<usize>> {
+    let mut matched = vec![None; cols];
+    for (block_rows, block_cols) in blocks(costs, rows, cols) {
+        let block: Vec<Option<f64>> = block_rows
+            .iter()
+            .flat_map(|&i| block_cols.iter().map(move |&j| costs[i * cols + j]))
+            .collect();
+        // Once leaving a row or column unmatched costs more than all links
+        // together, it only asks for as many links as possible. Capping it
+        // there keeps the potentials finite and precise for any distance.
+        let total: f64 = block.iter().flatten().sum();
+        let unmatched = unmatched.min(total + 1.0);
+        let solution = solve(&block, block_rows.len(), block_cols.len(), unmatched);
+        for (&j, m) in block_cols.iter().zip(solution) {
+            matched[j] = m.map(|i| block_rows[i]);
+        }
+    }
+    matched
+}
+
+/// Groups rows and columns into the connected components of the admissible
+/// links. Rows and columns without any link are left out.
+fn blocks(costs: &[Option<f64>], rows: usize, cols: usize) -> Vec<(Vec<usize>, Vec<usize>)> {
+    // Union-find over the rows followed by the columns.
+    let mut parent: Vec<usize> = (0..rows + cols).collect();
+    fn find(parent: &mut [usize], mut k: usize) -> usize {
+        while parent[k] != k {
+            parent[k] = parent[parent[k]];
+            k = parent[k];
+        }
+        k
+    }
+    for (k, c) in costs.iter().enumerate() {
+        if c.is_some() {
+            let (a, b) = (
+                find(&mut parent, k / cols),
+                find(&mut parent, rows + k % cols),
+            );
+            parent[a.max(b)] = a.min(b);
+        }
+    }
+    let mut slots = vec![usize::MAX; rows + cols];
+    let mut blocks: Vec<(Vec<usize>, Vec<usize>)> = Vec::new();
+    for k in 0..rows + cols {
+        let root = find(&mut parent, k);
+        if root == k && k >= rows {
+            // A column that is its own root has no links.
+            continue;
+        }
+        if slots[root] == usize::MAX {
+            slots[root] = blocks.len();
+            blocks.push((Vec::new(), Vec::new()));
+        }
+        let block = &mut blocks[slots[root]];
+        if k < rows {
+            block.0.push(k);
+        } else {
+            block.1.push(k - rows);
+        }
+    }
+    blocks.retain(|(_, cols)| !cols.is_empty());
+    blocks
+}
+
+/// Solves the square assignment problem over the rows and columns padded
+/// with one dummy per column and row, where a row or column matched to its
+/// dummy is unmatched. Links without a cost are never used; pairing every
+/// row and column with its dummy always leaves a complete assignment.
+/// Returns the matched row of every column.
+fn solve(costs: &[Option<f64>], rows: usize, cols: usize, unmatched: f64) -> Vec<Option<usize>> {
+    let n = rows + cols;
+    let cost = |i: usize, j: usize| match (i < rows, j < cols) {
+        (true, true) => costs[i * cols + j],
+        (true, false) if j - cols == i => Some(unmatched),
+        (false, true) if i - rows == j => Some(unmatched),
+        (false, false) => Some(0.0),
+        _ => None,
+    };
+    // Shortest augmenting paths with row and column potentials, indexed
+    // from 1 so that 0 can stand for the unassigned start of each path.
+    let (mut u, mut v) = (vec![0.0; n + 1], vec![0.0; n + 1]);
+    let mut row_of = vec![0; n + 1];
+    let mut way = vec![0; n + 1];
+    for i in 1..=n {
+        row_of[0] = i;
+        let mut j0 = 0;
+        let mut min = vec![f64::INFINITY; n + 1];
+        let mut used = vec![false; n + 1];
+        loop {
+            used[j0] = true;
+            let i0 = row_of[j0];
+            let (mut delta, mut j1) = (f64::INFINITY, 0);
+            for j in 1..=n {
+                if !used[j] {
+                    if let Some(c) = cost(i0 - 1, j - 1) {
+                        let reduced = c - u[i0] - v[j];
+                        if reduced < min[j] {
+                            min[j] = reduced;
+                            way[j] = j0;
+                        }
+                    }
+                    if min[j] < delta {
+                        delta = min[j];
+                        j1 = j;
+                    }
+                }
+            }
+            for j in 0..=n {
+                if used[j] {
+                    u[row_of[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if row_of[j0] == 0 {
+                break;
+            }
+        }
+        while j0 != 0 {
+            let j1 = way[j0];
+            row_of[j0] = row_of[j1];
+            j0 = j1;
+        }
+    }
+    (1..=cols)
+        .map(|j| {
+            let i = row_of[j] - 1;
+            (i < rows).then_some(i)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_unbounded_displacement() {
+        for max_displacement in [f64::INFINITY, f64::NAN, 0.0, -1.0] {
+            let params = TrackParams {
+                max_displacement,
+                ..TrackParams::default()
+            };
+            assert!(params.validate().is_err(), "{max_displacement}");
+        }
+        assert!(TrackParams::default().validate().is_ok());
+    }
+
+    /// Used to hang when unmatched blobs cost an infinite distance.
+    #[test]
+    fn hungarian_terminates_with_infinite_displacement() {
+        let params = TrackParams {
+            linking: Linking::Hungarian,
+            max_displacement: f64::INFINITY,
+            max_gap: 0,
+        };
+        let frames = vec![
+            vec![(0.0, 0.0), (10.0, 0.0)],
+            vec![(1.0, 0.0)],
+            vec![(1.0, 1.0), (50.0, 50.0), (9.0, 0.0)],
+        ];
+        assert_eq!(
+            link(&frames, &params),
+            vec![vec![1, 2], vec![1], vec![1, 3, 4]]
+        );
+    }
+
+    #[test]
+    fn blocks_match_the_dense_solution() {
+        let mut state = 0x9e3779b97f4a7c15u64;
+        let mut rand = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        for _ in 0..200 {
+            let (rows, cols) = (1 + (rand() * 8.0) as usize, 1 + (rand() * 8.0) as usize);
+            let costs: Vec<Option<f64>> = (0..rows * cols)
+                .map(|_| (rand() < 0.3).then(|| rand() * 10.0))
+                .collect();
+            let total = |matched: &[Option<usize>]| {
+                let links: Vec<(usize, usize)> = matched
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(j, m)| m.map(|i| (i, j)))
+                    .collect();
+                let unmatched = rows + cols - 2 * links.len();
+                links
+                    .iter()
+                    .map(|&(i, j)| costs[i * cols + j].unwrap())
+                    .sum::<f64>()
+                    + unmatched as f64 * 10.0
+            };
+            let (split, dense) = (
+                hungarian(&costs, rows, cols, 10.0),
+                solve(&costs, rows, cols, 10.0),
+            );
+            assert!((total(&split) - total(&dense)).abs() < 1e-9);
+        }
+    }
+}
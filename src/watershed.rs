@@ -0,0 +1,176 @@
+use crate::Connectivity;
+use std::{cmp, collections::BinaryHeap};
+
+/// Stands in for an infinite distance while keeping the parabola arithmetic
+/// finite.
+const FAR: f64 = 1e20;
+
+/// Squared Euclidean distance transform of one line (Felzenszwalb &
+/// Huttenlocher): `d[q] = min_p (q - p)² + f[p]`.
+fn edt_line(f: &[f64], d: &mut [f64], v: &mut [usize], z: &mut [f64]) {
+    let parabola = |p: usize| f[p] + (p * p) as f64;
+    let mut k = 0;
+    v[0] = 0;
+    z[0] = f64::NEG_INFINITY;
+    z[1] = f64::INFINITY;
+    for q in 1..f.len() {
+        let mut s = (parabola(q) - parabola(v[k])) / (2 * (q - v[k])) as f64;
+        while s <= z[k] {
+            k -= 1;
+            s = (parabola(q) - parabola(v[k])) / (2 * (q - v[k])) as f64;
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f64::INFINITY;
+    }
+    k = 0;
+    for (q, out) in d.iter_mut().enumerate() {
+        while z[k + 1] < q as f64 {
+            k += 1;
+        }
+        *out = (q as f64 - v[k] as f64).powi(2) + f[v[k]];
+    }
+}
+
+/// Squared distance from every pixel of a `width` x `height` grid to the
+/// nearest pixel outside `inside`. The grid must have at least one outside
+/// pixel.
+fn squared_edt(inside: &[bool], width: usize, height: usize) -> Vec<f64> {
+    let mut grid: Vec<f64> = inside.iter().map(|&v| if v { FAR } else { 0.0 }).collect();
+    let n = width.max(height);
+    let (mut f, mut d) = (vec![0.0; n], vec![0.0; n]);
+    let (mut v, mut z) = (vec![0; n], vec![0.0; n + 1]);
+    for x in 0..width {
+        for y in 0..height {
+            f[y] = grid[y * width + x];
+        }
+        edt_line(&f[..height], &mut d[..height], &mut v, &mut z);
+        for y in 0..height {
+            grid[y * width + x] = d[y];
+        }
+    }
+    for row in grid.chunks_mut(width) {
+        f[..width].copy_from_slice(row);
+        edt_line(&f[..width], &mut d[..width], &mut v, &mut z);
+        row.copy_from_slice(&d[..width]);
+    }
+    grid
+}
+
+/// Splits a connected component into touching objects: the regional maxima
+/// of its Euclidean distance transform, each plateau of equal distance
+/// counting once, that lie at least `min_distance` apart become markers, and
+/// a marker-controlled watershed on the inverted distance map assigns every
+/// pixel to one of them. Components with a single marker are returned
+/// unchanged. Parts list their pixels in raster order.
+pub fn split(
+    pixels: Vec<(u32, u32)>,
+    connectivity: Connectivity,
+    min_distance: f64,
+) -> Vec<Vec<(u32, u32)>> {
+    let (x0, y0) = pixels
+        .iter()
+        .fold((u32::MAX, u32::MAX), |(ax, ay), &(x, y)| {
+            (ax.min(x), ay.min(y))
+        });
+    let (x1, y1) = pixels
+        .iter()
+        .fold((0, 0), |(ax, ay), &(x, y)| (ax.max(x), ay.max(y)));
+    // Pad by one pixel so that the grid always has a border of background.
+    let (w, h) = ((x1 - x0 + 3) as usize, (y1 - y0 + 3) as usize);
+    let index = |(x, y): (u32, u32)| (y - y0 + 1) as usize * w + (x - x0 + 1) as usize;
+    let mut inside = vec![false; w * h];
+    for &pt in &pixels {
+        inside[index(pt)] = true;
+    }
+    let dist = squared_edt(&inside, w, h);
+    let neighbours = |i: usize, conn: Connectivity| {
+        conn.offsets()
+            .iter()
+            .map(move |&(dx, dy)| (i as isize + dy as isize * w as isize + dx as isize) as usize)
+    };
+    // Regional maxima: plateaus of equal distance without a higher
+    // neighbour. A ridge, such as the spine of an elongated object, is one
+    // maximum rather than a peak at every pixel.
+    let mut seen = vec![false; w * h];
+    let mut maxima: Vec<(usize, Vec<usize>)> = Vec::new();
+    for i in 0..w * h {
+        if !inside[i] || seen[i] {
+            continue;
+        }
+        seen[i] = true;
+        let (mut plateau, mut highest) = (vec![i], true);
+        let mut k = 0;
+        while k < plateau.len() {
+            let p = plateau[k];
+            k += 1;
+            for j in neighbours(p, Connectivity::Eight) {
+                if dist[j] > dist[p] {
+                    highest = false;
+                } else if dist[j] == dist[p] && !seen[j] {
+                    seen[j] = true;
+                    plateau.push(j);
+                }
+            }
+        }
+        if highest {
+            // Stand in for the plateau with its pixel closest to its centre.
+            let n = plateau.len() as f64;
+            let (cx, cy) = plateau.iter().fold((0.0, 0.0), |(ax, ay), &p| {
+                (ax + (p % w) as f64 / n, ay + (p / w) as f64 / n)
+            });
+            let centre = *plateau
+                .iter()
+                .min_by(|&&a, &&b| {
+                    let d = |p: usize| ((p % w) as f64 - cx).hypot((p / w) as f64 - cy);
+                    d(a).total_cmp(&d(b))
+                })
+                .unwrap();
+            maxima.push((centre, plateau));
+        }
+    }
+    maxima.sort_by(|a, b| dist[b.0].total_cmp(&dist[a.0]).then(a.1[0].cmp(&b.1[0])));
+    let mut markers: Vec<(usize, Vec<usize>)> = Vec::new();
+    for (peak, plateau) in maxima {
+        let (px, py) = ((peak % w) as f64, (peak / w) as f64);
+        if markers
+            .iter()
+            .all(|&(m, _)| ((m % w) as f64 - px).hypot((m / w) as f64 - py) >= min_distance)
+        {
+            markers.push((peak, plateau));
+        }
+    }
+    if markers.len() < 2 {
+        return vec![pixels];
+    }
+    // Priority flood from the markers, deepest distance first and in order
+    // of arrival among equals. Squared distances inside the component are
+    // whole numbers, so they order exactly as integers.
+    let mut labels = vec![0; w * h];
+    let mut queue = BinaryHeap::new();
+    let mut order = 0;
+    for (i, (_, plateau)) in markers.iter().enumerate() {
+        for &m in plateau {
+            labels[m] = i + 1;
+            queue.push((dist[m] as u64, cmp::Reverse(order), m));
+            order += 1;
+        }
+    }
+    while let Some((_, _, i)) = queue.pop() {
+        for j in neighbours(i, connectivity) {
+            if inside[j] && labels[j] == 0 {
+                labels[j] = labels[i];
+                queue.push((dist[j] as u64, cmp::Reverse(order), j));
+                order += 1;
+            }
+        }
+    }
+    let mut parts = vec![Vec::new(); markers.len()];
+    let mut pixels = pixels;
+    pixels.sort_unstable_by_key(|&(x, y)| (y, x));
+    for pt in pixels {
+        parts[labels[index(pt)] - 1].push(pt);
+    }
+    parts
+}